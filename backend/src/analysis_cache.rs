@@ -0,0 +1,42 @@
+//! Content-hash based dedup: the same mix+reference file bytes always hash
+//! the same way, so a user re-uploading (or re-running `reanalyze`) an
+//! unchanged pair gets the prior result back instead of paying for a fresh
+//! Essentia/Demucs/OpenAI run.
+
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// Hash the mix and reference file bytes together, so the same pair always
+/// produces the same key regardless of filename or upload path.
+pub fn content_hash(mix_bytes: &[u8], ref_bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(mix_bytes);
+    hasher.update(ref_bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+/// A previously-completed analysis that can stand in for a fresh run.
+pub struct CachedAnalysis {
+    pub metrics: serde_json::Value,
+    pub ai_report: String,
+    /// The stem-separation job that produced this result's stems, if any —
+    /// reused instead of re-running Demucs.
+    pub stem_job_id: Option<String>,
+}
+
+/// Look up the most recent completed analysis for `hash`, if any.
+pub async fn find(pool: &PgPool, hash: &str) -> Result<Option<CachedAnalysis>, String> {
+    sqlx::query_as!(
+        CachedAnalysis,
+        r#"SELECT a.metrics, a.ai_report, mv.stem_job_id
+           FROM analyses a
+           JOIN mix_versions mv ON mv.id = a.mix_version_id
+           WHERE a.content_hash = $1
+           ORDER BY a.created_at DESC
+           LIMIT 1"#,
+        hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to look up cached analysis: {}", e))
+}
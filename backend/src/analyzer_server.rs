@@ -0,0 +1,150 @@
+//! Long-lived `analyze_audio.py` worker, so `analyze_pair` doesn't pay
+//! Python + essentia/librosa import startup cost on every call — the
+//! dominant cost for short clips. Borrows the "exec server on a loopback
+//! port with token authentication" design from the VS Code CLI: the worker
+//! is spawned once in `--serve` mode, binds a loopback TCP port, and prints
+//! that port back to us on stdout; a random per-process token is handed to
+//! it via env var and must be echoed on every request, so no other local
+//! process can drive it. A crashed or unresponsive worker is transparently
+//! respawned on the next call.
+
+use crate::audio_analysis::{ComparisonResult, ScriptOutput};
+use crate::python_env;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a single request/response round-trip may take before the
+/// connection is considered dead and the worker gets respawned.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+struct Worker {
+    child: Child,
+    port: u16,
+    token: String,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Graceful shutdown: the child is ours alone (it was spawned with a
+        // token only we know), so there's nothing to negotiate — just kill
+        // it and reap it so it doesn't linger as a zombie.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+static WORKER: Mutex<Option<Worker>> = Mutex::new(None);
+
+#[derive(Serialize)]
+struct WorkerRequest<'a> {
+    token: &'a str,
+    mix: &'a str,
+    reference: &'a str,
+}
+
+/// Analyze a mix/reference pair through the persistent worker, starting one
+/// if none is running yet, and transparently respawning + retrying once if
+/// the existing worker turns out to be dead or unresponsive.
+pub fn analyze(mix_path: &str, reference_path: &str) -> Result<ComparisonResult, String> {
+    let mut guard = WORKER.lock().map_err(|_| "Analyzer worker lock poisoned".to_string())?;
+
+    if !matches!(guard.as_mut(), Some(worker) if is_alive(worker)) {
+        *guard = Some(spawn_worker()?);
+    }
+
+    let worker = guard.as_ref().expect("worker was just spawned or confirmed alive");
+    match send_request(worker, mix_path, reference_path) {
+        Ok(result) => Ok(result),
+        Err(e) => {
+            eprintln!("[analyzer_server] request failed ({}), respawning worker", e);
+            *guard = Some(spawn_worker()?);
+            let worker = guard.as_ref().expect("worker was just respawned");
+            send_request(worker, mix_path, reference_path)
+        }
+    }
+}
+
+/// Stop the worker, if one is running. Safe to call even if none is up.
+pub fn shutdown() {
+    if let Ok(mut guard) = WORKER.lock() {
+        *guard = None; // `Worker::drop` kills and reaps the child.
+    }
+}
+
+fn is_alive(worker: &mut Worker) -> bool {
+    matches!(worker.child.try_wait(), Ok(None))
+}
+
+#[derive(serde::Deserialize)]
+struct PortAnnouncement {
+    port: u16,
+}
+
+fn spawn_worker() -> Result<Worker, String> {
+    let python = python_env::resolve_python()?;
+    let script = python_env::resolve_script(&python, "analyze_audio.py");
+    let token = Uuid::new_v4().to_string();
+
+    let mut child = Command::new(&python)
+        .arg(&script)
+        .arg("--serve")
+        .arg("--port")
+        .arg("0")
+        .env("MIXANALYZER_WORKER_TOKEN", &token)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start analyzer worker: {}", e))?;
+
+    // The worker's first stdout line announces the loopback port it bound,
+    // so an ephemeral port (`--port 0`) works without a second channel.
+    let stdout = child.stdout.take().ok_or("Analyzer worker has no stdout")?;
+    let mut announcement_line = String::new();
+    BufReader::new(stdout)
+        .read_line(&mut announcement_line)
+        .map_err(|e| format!("Failed to read analyzer worker port announcement: {}", e))?;
+
+    let announcement: PortAnnouncement = serde_json::from_str(announcement_line.trim()).map_err(|e| {
+        format!(
+            "Failed to parse analyzer worker port announcement: {} (line: {:?})",
+            e, announcement_line
+        )
+    })?;
+
+    Ok(Worker { child, port: announcement.port, token })
+}
+
+fn send_request(worker: &Worker, mix_path: &str, reference_path: &str) -> Result<ComparisonResult, String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", worker.port))
+        .map_err(|e| format!("Failed to connect to analyzer worker on port {}: {}", worker.port, e))?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT)).ok();
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT)).ok();
+
+    let request = WorkerRequest { token: &worker.token, mix: mix_path, reference: reference_path };
+    let mut line = serde_json::to_string(&request).map_err(|e| format!("Failed to encode worker request: {}", e))?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).map_err(|e| format!("Failed to send request to worker: {}", e))?;
+
+    let mut response_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response_line)
+        .map_err(|e| format!("Failed to read response from worker: {}", e))?;
+
+    if response_line.is_empty() {
+        return Err("Analyzer worker closed the connection without responding".to_string());
+    }
+
+    let response: ScriptOutput = serde_json::from_str(response_line.trim())
+        .map_err(|e| format!("Failed to parse worker response: {} (line: {:?})", e, response_line))?;
+
+    if let Some(err) = response.error {
+        return Err(format!("Analysis error: {}", err));
+    }
+
+    Ok(ComparisonResult { mix: response.mix, reference: response.reference })
+}
@@ -1,5 +1,7 @@
+use crate::python_env;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -32,45 +34,362 @@ pub struct ComparisonResult {
     pub reference: AudioMetrics,
 }
 
+/// How urgently a [`Recommendation`] should be addressed. Ordered so
+/// sorting a `Vec<Recommendation>` by severity surfaces `Critical` items
+/// (things that risk clipping/distortion) ahead of stylistic `Info` notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Critical,
+    Warning,
+    Info,
+}
+
+/// One actionable piece of mixing advice derived from a mix/reference
+/// metric gap, so the frontend can render a prioritized checklist instead
+/// of raw numbers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recommendation {
+    /// `AudioMetrics` field this recommendation was derived from, e.g.
+    /// `"integrated_lufs"`.
+    pub metric: String,
+    pub measured: f32,
+    pub target: f32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ComparisonResult {
+    /// Diff the mix against the reference and turn the gaps that exceed a
+    /// sensible threshold into actionable advice, sorted most-severe first.
+    pub fn recommendations(&self) -> Vec<Recommendation> {
+        let mut recs = Vec::new();
+
+        let lufs_delta = self.mix.integrated_lufs - self.reference.integrated_lufs;
+        if lufs_delta.abs() > 1.0 {
+            let verb = if lufs_delta < 0.0 { "raise" } else { "lower" };
+            recs.push(Recommendation {
+                metric: "integrated_lufs".to_string(),
+                measured: self.mix.integrated_lufs,
+                target: self.reference.integrated_lufs,
+                severity: Severity::Warning,
+                message: format!(
+                    "{verb} gain by {:.1} dB to match the reference loudness ({:.1} LUFS vs {:.1} LUFS)",
+                    lufs_delta.abs(),
+                    self.mix.integrated_lufs,
+                    self.reference.integrated_lufs,
+                ),
+            });
+        }
+
+        const TRUE_PEAK_CEILING_DBTP: f32 = -1.0;
+        if self.mix.true_peak > TRUE_PEAK_CEILING_DBTP {
+            recs.push(Recommendation {
+                metric: "true_peak".to_string(),
+                measured: self.mix.true_peak,
+                target: TRUE_PEAK_CEILING_DBTP,
+                severity: Severity::Critical,
+                message: format!(
+                    "True peak is {:.1} dBTP, above the {:.1} dBTP safety margin — engage a true-peak limiter to avoid inter-sample clipping",
+                    self.mix.true_peak, TRUE_PEAK_CEILING_DBTP,
+                ),
+            });
+        }
+
+        const SPECTRAL_CENTROID_GAP_HZ: f32 = 500.0;
+        let centroid_delta = self.mix.spectral_centroid - self.reference.spectral_centroid;
+        if centroid_delta.abs() > SPECTRAL_CENTROID_GAP_HZ {
+            let (direction, adjust) =
+                if centroid_delta > 0.0 { ("brighter", "cut") } else { ("darker", "boost") };
+            recs.push(Recommendation {
+                metric: "spectral_centroid".to_string(),
+                measured: self.mix.spectral_centroid,
+                target: self.reference.spectral_centroid,
+                severity: Severity::Info,
+                message: format!(
+                    "The mix is {direction} than the reference ({:.0} Hz vs {:.0} Hz) — {adjust} the high shelf to compensate",
+                    self.mix.spectral_centroid, self.reference.spectral_centroid,
+                ),
+            });
+        }
+
+        const CONCERT_PITCH_HZ: f32 = 440.0;
+        const TUNING_TOLERANCE_HZ: f32 = 1.0;
+        if (self.reference.tuning_frequency - CONCERT_PITCH_HZ).abs() > TUNING_TOLERANCE_HZ {
+            recs.push(Recommendation {
+                metric: "tuning_frequency".to_string(),
+                measured: self.reference.tuning_frequency,
+                target: CONCERT_PITCH_HZ,
+                severity: Severity::Info,
+                message: format!(
+                    "Reference track is tuned to {:.1} Hz, not the standard {CONCERT_PITCH_HZ:.0} Hz — account for this before matching pitch-sensitive elements (mix measured {:.1} Hz)",
+                    self.reference.tuning_frequency, self.mix.tuning_frequency,
+                ),
+            });
+        }
+
+        recs.sort_by_key(|r| r.severity);
+        recs
+    }
+}
+
+/// `analyze_audio.py`'s JSON response shape, both for a one-shot run and for
+/// each request/response line the persistent worker in `analyzer_server`
+/// exchanges over its socket.
 #[derive(Deserialize, Debug)]
-struct ScriptOutput {
-    mix: AudioMetrics,
-    reference: AudioMetrics,
+pub(crate) struct ScriptOutput {
+    pub(crate) mix: AudioMetrics,
+    pub(crate) reference: AudioMetrics,
     #[serde(default)]
-    error: Option<String>,
+    pub(crate) error: Option<String>,
+}
+
+/// Python packages `analyze_audio.py` imports; probed by [`check_environment`]
+/// so a missing one surfaces as a clear "install X" message instead of the
+/// subprocess dying with an opaque traceback on stderr.
+const REQUIRED_PACKAGES: &[&str] = &["essentia", "librosa"];
+
+/// Result of probing the resolved interpreter for the packages
+/// `analyze_audio.py` needs, conda/rattler-build `imports`-test style:
+/// launch the interpreter with `-c "import <package>"` per candidate rather
+/// than trying to parse `pip list` output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvReport {
+    pub python_version: String,
+    pub found_packages: Vec<String>,
+    pub missing_packages: Vec<String>,
 }
 
+impl EnvReport {
+    pub fn is_ready(&self) -> bool {
+        self.missing_packages.is_empty()
+    }
+}
+
+/// Resolve the Python interpreter and probe it for every package
+/// `analyze_audio.py` needs. Cheap enough to run up front (one `--version`
+/// call plus one `-c "import ..."` call per package) so the frontend can
+/// show a precise "install X" message before the user ever uploads a file.
+pub fn check_environment() -> Result<EnvReport, String> {
+    let python = python_env::resolve_python()?;
+
+    let version_output = Command::new(&python)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("Failed to execute python: {}", e))?;
+    // Older Python builds print `--version` to stderr instead of stdout.
+    let python_version = {
+        let stdout = String::from_utf8_lossy(&version_output.stdout).trim().to_string();
+        if stdout.is_empty() {
+            String::from_utf8_lossy(&version_output.stderr).trim().to_string()
+        } else {
+            stdout
+        }
+    };
+
+    let mut found_packages = Vec::new();
+    let mut missing_packages = Vec::new();
+    for package in REQUIRED_PACKAGES {
+        let probe = Command::new(&python)
+            .arg("-c")
+            .arg(format!("import {}", package))
+            .output()
+            .map_err(|e| format!("Failed to execute python: {}", e))?;
+        if probe.status.success() {
+            found_packages.push(package.to_string());
+        } else {
+            missing_packages.push(package.to_string());
+        }
+    }
+
+    Ok(EnvReport { python_version, found_packages, missing_packages })
+}
+
+/// Runs analysis on a mix/reference pair via the persistent worker in
+/// `analyzer_server` rather than spawning a fresh `analyze_audio.py`
+/// process per call — avoiding the essentia/librosa import cost on every
+/// request, which otherwise dominates runtime for short clips. Deliberately
+/// does not probe the environment here (that's three more subprocess spawns
+/// of exactly the cost the worker exists to eliminate): `check_environment`
+/// is for the `/api/doctor` endpoint's explicit, user-initiated check, and a
+/// missing package on this path surfaces instead as a worker failure from
+/// `analyzer_server::analyze` itself.
 pub fn analyze_pair<P: AsRef<Path>>(mix_path: P, ref_path: P) -> Result<ComparisonResult, String> {
     let mix_str = mix_path.as_ref().to_str().ok_or("Invalid mix path")?;
     let ref_str = ref_path.as_ref().to_str().ok_or("Invalid reference path")?;
 
-    // Call Python script using the project's virtual environment
-    // Assuming the backend is running from `backend/` and .venv is in the project root `../.venv`
-    // Or if running from root, it's `.venv`.
-    // Safest is to try to resolve it or use a relative path from the backend dir.
-    // Since `cargo run` is usually from `backend/`, the path to venv is `../.venv/bin/python`
-    let output = Command::new("../.venv/bin/python")
-        .arg("analyze_audio.py")
-        .arg(mix_str)
-        .arg(ref_str)
-        .output()
-        .map_err(|e| format!("Failed to execute python script: {}", e))?;
+    crate::analyzer_server::analyze(mix_str, ref_str)
+}
+
+/// Bump whenever `ScriptOutput`'s shape (or `analyze_audio.py`'s behavior
+/// for the same input) changes, so stale cache entries from an older
+/// analyzer version don't get served after an upgrade.
+const CACHE_SCHEMA_VERSION: &str = "v1";
+
+/// Cheap stand-in for a full content hash, in the spirit of Cargo build
+/// scripts' `rerun-if-changed`: a file's size+mtime is enough to detect that
+/// it changed without reading and hashing potentially-huge audio files on
+/// every call.
+fn file_fingerprint(path: &Path) -> Result<String, String> {
+    let meta = std::fs::metadata(path).map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?;
+    let modified = meta
+        .modified()
+        .map_err(|e| format!("Failed to read mtime for {}: {}", path.display(), e))?;
+    let mtime_secs = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("Invalid mtime for {}: {}", path.display(), e))?
+        .as_secs();
+    Ok(format!("{}:{}", meta.len(), mtime_secs))
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Python script failed: {}", stderr));
+fn cache_key(mix_path: &Path, ref_path: &Path) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    hasher.update(CACHE_SCHEMA_VERSION.as_bytes());
+    hasher.update(file_fingerprint(mix_path)?.as_bytes());
+    hasher.update(file_fingerprint(ref_path)?.as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// `~/.cache/mixanalyzer` (or `$XDG_CACHE_HOME/mixanalyzer` when set),
+/// created on first write.
+fn cache_dir() -> PathBuf {
+    if let Ok(xdg_cache) = std::env::var("XDG_CACHE_HOME") {
+        return PathBuf::from(xdg_cache).join("mixanalyzer");
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join(".cache").join("mixanalyzer")
+}
+
+/// Same as [`analyze_pair`], but skips the Python subprocess entirely when
+/// an unchanged mix/reference pair was already analyzed: the cache key is
+/// derived from each file's size+mtime (see [`file_fingerprint`]) plus
+/// [`CACHE_SCHEMA_VERSION`], so touching either input or upgrading the
+/// analyzer invalidates the entry automatically.
+pub fn analyze_pair_cached<P: AsRef<Path>>(mix_path: P, ref_path: P) -> Result<ComparisonResult, String> {
+    let mix_path = mix_path.as_ref();
+    let ref_path = ref_path.as_ref();
+    let key = cache_key(mix_path, ref_path)?;
+    let cache_file = cache_dir().join(format!("{}.json", key));
+
+    if let Ok(cached) = std::fs::read(&cache_file) {
+        if let Ok(result) = serde_json::from_slice::<ComparisonResult>(&cached) {
+            return Ok(result);
+        }
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let result: ScriptOutput = serde_json::from_str(&stdout)
-        .map_err(|e| format!("Failed to parse JSON output: {} (Output: {})", e, stdout))?;
+    let result = analyze_pair(mix_path, ref_path)?;
 
-    if let Some(err) = result.error {
-        return Err(format!("Analysis error: {}", err));
+    if let Err(e) = write_cache(&cache_file, &result) {
+        eprintln!("[audio_analysis] failed to write cache entry {}: {}", cache_file.display(), e);
     }
 
-    Ok(ComparisonResult {
-        mix: result.mix,
-        reference: result.reference,
-    })
+    Ok(result)
+}
+
+fn write_cache(cache_file: &Path, result: &ComparisonResult) -> Result<(), String> {
+    let dir = cache_file.parent().ok_or("Cache file has no parent directory")?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create cache dir {}: {}", dir.display(), e))?;
+    let json = serde_json::to_vec(result).map_err(|e| format!("Failed to serialize cache entry: {}", e))?;
+    std::fs::write(cache_file, json).map_err(|e| format!("Failed to write {}: {}", cache_file.display(), e))
+}
+
+/// Remove every cached `analyze_pair_cached` result.
+pub fn clear_cache() -> Result<(), String> {
+    let dir = cache_dir();
+    if !dir.exists() {
+        return Ok(());
+    }
+    std::fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear cache dir {}: {}", dir.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(
+        integrated_lufs: f32,
+        true_peak: f32,
+        spectral_centroid: f32,
+        tuning_frequency: f32,
+    ) -> AudioMetrics {
+        AudioMetrics {
+            integrated_lufs,
+            loudness_range: 8.0,
+            true_peak,
+            dynamic_complexity: 5.0,
+            bpm: 120.0,
+            beat_confidence: 1.0,
+            danceability: 0.5,
+            key: "C".to_string(),
+            scale: "major".to_string(),
+            tuning_frequency,
+            spectral_centroid,
+            spectral_rolloff: 8000.0,
+            spectral_flux: 0.1,
+        }
+    }
+
+    fn matched_comparison() -> ComparisonResult {
+        ComparisonResult {
+            mix: metrics(-14.0, -2.0, 2000.0, 440.0),
+            reference: metrics(-14.0, -2.0, 2000.0, 440.0),
+        }
+    }
+
+    #[test]
+    fn test_recommendations_empty_when_within_thresholds() {
+        assert!(matched_comparison().recommendations().is_empty());
+    }
+
+    #[test]
+    fn test_recommendations_flags_loudness_gap() {
+        let mut comparison = matched_comparison();
+        comparison.mix.integrated_lufs = -10.0; // 4 LU louder than reference
+        let recs = comparison.recommendations();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].metric, "integrated_lufs");
+        assert_eq!(recs[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_recommendations_flags_true_peak_as_critical() {
+        let mut comparison = matched_comparison();
+        comparison.mix.true_peak = -0.5; // above the -1.0 dBTP ceiling
+        let recs = comparison.recommendations();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].metric, "true_peak");
+        assert_eq!(recs[0].severity, Severity::Critical);
+    }
+
+    #[test]
+    fn test_recommendations_flags_spectral_centroid_gap() {
+        let mut comparison = matched_comparison();
+        comparison.mix.spectral_centroid = 2600.0; // 600 Hz brighter than reference
+        let recs = comparison.recommendations();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].metric, "spectral_centroid");
+        assert_eq!(recs[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn test_recommendations_flags_reference_off_concert_pitch() {
+        let mut comparison = matched_comparison();
+        comparison.reference.tuning_frequency = 442.0; // 2 Hz from 440 Hz
+        let recs = comparison.recommendations();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].metric, "tuning_frequency");
+        assert_eq!(recs[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn test_recommendations_sorted_most_severe_first() {
+        let mut comparison = matched_comparison();
+        comparison.mix.spectral_centroid = 2600.0; // Info
+        comparison.mix.true_peak = -0.5; // Critical
+        comparison.mix.integrated_lufs = -10.0; // Warning
+        let recs = comparison.recommendations();
+        assert_eq!(recs.len(), 3);
+        assert_eq!(recs[0].severity, Severity::Critical);
+        assert_eq!(recs[1].severity, Severity::Warning);
+        assert_eq!(recs[2].severity, Severity::Info);
+    }
 }
@@ -0,0 +1,168 @@
+//! Reproducible benchmark harness for the mix/reference analysis pipeline.
+//!
+//! Each workload is a JSON file naming a mix path, a reference path, and
+//! expected metric tolerances. Run with:
+//!
+//!     cargo run --bin bench -- workloads/*.json
+//!
+//! and get back a machine-readable report of per-stage timings plus
+//! pass/fail against the declared tolerances, so regressions in analysis
+//! latency or correctness are measurable across changes to the Demucs
+//! integration and metric code.
+
+#[path = "../python_env.rs"]
+mod python_env;
+#[path = "../audio_analysis.rs"]
+mod audio_analysis;
+#[path = "../analyzer_server.rs"]
+mod analyzer_server;
+#[path = "../stem_separation.rs"]
+mod stem_separation;
+
+use audio_analysis::analyze_pair;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Debug, Deserialize)]
+struct Workload {
+    name: Option<String>,
+    mix: PathBuf,
+    reference: PathBuf,
+    #[serde(default)]
+    tolerances: Tolerances,
+    /// When true, also benchmark stem separation on the mix file.
+    #[serde(default)]
+    include_stem_separation: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tolerances {
+    #[serde(default = "default_lufs_tolerance")]
+    integrated_lufs: f32,
+    #[serde(default = "default_bpm_tolerance")]
+    bpm: f32,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Self { integrated_lufs: default_lufs_tolerance(), bpm: default_bpm_tolerance() }
+    }
+}
+
+fn default_lufs_tolerance() -> f32 {
+    1.0
+}
+
+fn default_bpm_tolerance() -> f32 {
+    2.0
+}
+
+#[derive(Debug, Serialize)]
+struct StageTiming {
+    stage: String,
+    elapsed_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkloadReport {
+    name: String,
+    pass: bool,
+    stages: Vec<StageTiming>,
+    failures: Vec<String>,
+}
+
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: bench <workload.json>...");
+        std::process::exit(2);
+    }
+
+    let mut reports = Vec::new();
+    let mut any_failed = false;
+
+    for path in &paths {
+        match run_workload(path) {
+            Ok(report) => {
+                any_failed |= !report.pass;
+                reports.push(report);
+            }
+            Err(e) => {
+                eprintln!("[bench] failed to run workload {}: {}", path, e);
+                any_failed = true;
+            }
+        }
+    }
+
+    println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    std::process::exit(if any_failed { 1 } else { 0 });
+}
+
+fn run_workload(path: &str) -> Result<WorkloadReport, String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let workload: Workload =
+        serde_json::from_str(&raw).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+
+    let name = workload.name.clone().unwrap_or_else(|| path.to_string());
+    let mut stages = Vec::new();
+    let mut failures = Vec::new();
+
+    let start = Instant::now();
+    let comparison = analyze_pair(&workload.mix, &workload.reference);
+    stages.push(StageTiming {
+        stage: "essentia_analysis".to_string(),
+        elapsed_ms: start.elapsed().as_millis(),
+    });
+
+    let comparison = match comparison {
+        Ok(c) => c,
+        Err(e) => {
+            failures.push(format!("analysis failed: {}", e));
+            return Ok(WorkloadReport { name, pass: false, stages, failures });
+        }
+    };
+
+    if workload.include_stem_separation {
+        let out_dir = std::env::temp_dir().join(format!("bench-stems-{}", std::process::id()));
+        let start = Instant::now();
+        let result = stem_separation::separate_stems_sync(&workload.mix, &out_dir);
+        stages.push(StageTiming {
+            stage: "stem_separation".to_string(),
+            elapsed_ms: start.elapsed().as_millis(),
+        });
+        if let Err(e) = result {
+            failures.push(format!("stem separation failed: {}", e));
+        }
+        let _ = std::fs::remove_dir_all(&out_dir);
+    }
+
+    check_tolerance(
+        "mix.integrated_lufs",
+        comparison.mix.integrated_lufs,
+        comparison.reference.integrated_lufs,
+        workload.tolerances.integrated_lufs,
+        &mut failures,
+    );
+    check_tolerance(
+        "mix.bpm",
+        comparison.mix.bpm,
+        comparison.reference.bpm,
+        workload.tolerances.bpm,
+        &mut failures,
+    );
+
+    let pass = failures.is_empty();
+    Ok(WorkloadReport { name, pass, stages, failures })
+}
+
+fn check_tolerance(field: &str, measured: f32, expected: f32, tolerance: f32, failures: &mut Vec<String>) {
+    let delta = (measured - expected).abs();
+    if delta > tolerance {
+        failures.push(format!(
+            "{} out of tolerance: measured {:.2}, expected {:.2} (tolerance {:.2}, delta {:.2})",
+            field, measured, expected, tolerance, delta
+        ));
+    }
+}
+
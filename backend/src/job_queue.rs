@@ -0,0 +1,254 @@
+//! Durable job queue backed by the `job_queue` Postgres table, so background
+//! work (analysis and stem separation) survives a server restart and can be
+//! retried.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Default maximum number of attempts (the first run included) before a job
+/// is marked `Failed`, overridable via `MIXANALYZER_JOB_MAX_RETRIES`.
+pub const DEFAULT_MAX_RETRIES: i32 = 5;
+
+/// Longest a retry is ever delayed, regardless of attempt count.
+pub const BACKOFF_CEILING_SECS: i64 = 300;
+
+/// How long a `running` job can go without a heartbeat before the reaper
+/// assumes the worker crashed and puts it back in the queue.
+pub const STALE_TIMEOUT_SECS: i64 = 120;
+
+pub fn max_retries() -> i32 {
+    std::env::var("MIXANALYZER_JOB_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_RETRIES)
+}
+
+/// Exponential backoff for the next retry, in seconds: `2^attempt`, capped at
+/// `BACKOFF_CEILING_SECS`.
+pub fn backoff_delay_secs(attempt: i32) -> i64 {
+    2i64.saturating_pow(attempt.max(0) as u32).min(BACKOFF_CEILING_SECS)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "job_status", rename_all = "lowercase")]
+pub enum JobQueueStatus {
+    New,
+    Running,
+    Completed,
+    Failed,
+    /// Terminal: the row's `job` payload could not be deserialized at all
+    /// (e.g. after a breaking schema change), so it can never succeed no
+    /// matter how many times it's retried. See `dead_letter`.
+    Invalid,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct JobQueueRow {
+    pub id: Uuid,
+    pub queue: String,
+    pub job: Value,
+    pub status: JobQueueStatus,
+    pub retries: i32,
+    pub heartbeat: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The `job` payload of a row marked `invalid`: the original, undeserializable
+/// value plus the error that made it so, preserved for debugging.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub error: String,
+    pub raw_job: Value,
+}
+
+/// Insert a new `new` row onto `queue`, returning the row id.
+pub async fn enqueue(pool: &PgPool, queue: &str, job: &Value) -> Result<Uuid, String> {
+    let rec = sqlx::query!(
+        "INSERT INTO job_queue (queue, job, status) VALUES ($1, $2, 'new') RETURNING id",
+        queue,
+        job
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to enqueue job: {}", e))?;
+
+    Ok(rec.id)
+}
+
+/// Atomically claim the oldest `new` row on `queue` whose backoff has
+/// elapsed, marking it `running`.
+pub async fn claim_job(pool: &PgPool, queue: &str) -> Result<Option<JobQueueRow>, String> {
+    sqlx::query_as!(
+        JobQueueRow,
+        r#"UPDATE job_queue
+           SET status = 'running', heartbeat = now()
+           WHERE id = (
+               SELECT id FROM job_queue
+               WHERE queue = $1 AND status = 'new'
+                 AND (next_attempt_at IS NULL OR next_attempt_at <= now())
+               ORDER BY created_at
+               LIMIT 1
+               FOR UPDATE SKIP LOCKED
+           )
+           RETURNING id, queue, job, status as "status: _", retries, heartbeat, created_at"#,
+        queue
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to claim job: {}", e))
+}
+
+/// Persist live progress into the row's `job` JSONB and refresh the heartbeat,
+/// so a reconnecting client (or the reaper) can see where the job is.
+pub async fn update_progress(pool: &PgPool, id: Uuid, job: &Value) -> Result<(), String> {
+    sqlx::query!(
+        "UPDATE job_queue SET job = $1, heartbeat = now() WHERE id = $2",
+        job,
+        id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Failed to persist job progress: {}", e))
+}
+
+/// Mark a row `completed`, overwriting `job` with its final value so status
+/// endpoints and SSE streams can read the result straight back out of the
+/// table instead of an in-memory map.
+pub async fn complete_job(pool: &PgPool, id: Uuid, final_job: &Value) -> Result<(), String> {
+    sqlx::query!(
+        "UPDATE job_queue SET status = 'completed', job = $1, heartbeat = now() WHERE id = $2",
+        final_job,
+        id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Failed to mark job completed: {}", e))
+}
+
+/// On a *retryable* error, requeue the job with exponential backoff, or mark
+/// it `failed` once `max_retries()` has been exhausted. For a non-retryable
+/// error (a payload that doesn't even deserialize), use `dead_letter`
+/// instead — retrying it would just fail the same way forever.
+///
+/// Returns `true` if the job was permanently marked `failed`, `false` if it
+/// was requeued for another attempt — callers use this to decide whether a
+/// failure notification should be terminal or just a retry heads-up.
+pub async fn fail_or_retry(pool: &PgPool, id: Uuid, error: &str) -> Result<bool, String> {
+    let row = sqlx::query!("SELECT retries FROM job_queue WHERE id = $1", id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Failed to load job before retry: {}", e))?;
+
+    let Some(row) = row else {
+        return Ok(false);
+    };
+
+    if row.retries + 1 >= max_retries() {
+        sqlx::query!(
+            "UPDATE job_queue
+             SET status = 'failed', retries = retries + 1,
+                 job = jsonb_set(job, '{error}', to_jsonb($1::text))
+             WHERE id = $2",
+            error,
+            id
+        )
+        .execute(pool)
+        .await
+        .map(|_| true)
+        .map_err(|e| format!("Failed to mark job failed: {}", e))
+    } else {
+        let delay_secs = backoff_delay_secs(row.retries + 1) as f64;
+        sqlx::query!(
+            "UPDATE job_queue
+             SET status = 'new', retries = retries + 1,
+                 next_attempt_at = now() + make_interval(secs => $1)
+             WHERE id = $2",
+            delay_secs,
+            id
+        )
+        .execute(pool)
+        .await
+        .map(|_| false)
+        .map_err(|e| format!("Failed to requeue job: {}", e))
+    }
+}
+
+/// Permanently mark a row `invalid`: its `job` payload couldn't be
+/// deserialized, so no number of retries would ever help. Preserves the raw
+/// payload and the deserialization error for debugging.
+pub async fn dead_letter(pool: &PgPool, id: Uuid, raw_job: &Value, error: &str) -> Result<(), String> {
+    let payload = serde_json::to_value(DeadLetter {
+        error: error.to_string(),
+        raw_job: raw_job.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize dead letter: {}", e))?;
+
+    sqlx::query!(
+        "UPDATE job_queue SET status = 'invalid', job = $1 WHERE id = $2",
+        payload,
+        id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Failed to mark job invalid: {}", e))
+}
+
+/// Reset any `running` row whose heartbeat is older than `STALE_TIMEOUT_SECS`
+/// back to `new`, so a crashed worker's jobs self-heal instead of hanging
+/// forever.
+pub async fn reap_stale(pool: &PgPool) -> Result<u64, String> {
+    let result = sqlx::query!(
+        "UPDATE job_queue
+         SET status = 'new'
+         WHERE status = 'running'
+           AND heartbeat < now() - make_interval(secs => $1)",
+        STALE_TIMEOUT_SECS as f64
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to reap stale jobs: {}", e))?;
+
+    Ok(result.rows_affected())
+}
+
+/// Look up a row by id regardless of status, for status-reporting endpoints.
+pub async fn find(pool: &PgPool, id: Uuid) -> Result<Option<JobQueueRow>, String> {
+    sqlx::query_as!(
+        JobQueueRow,
+        r#"SELECT id, queue, job, status as "status: _", retries, heartbeat, created_at
+           FROM job_queue WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to look up job: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay_doubles() {
+        assert_eq!(backoff_delay_secs(0), 1);
+        assert_eq!(backoff_delay_secs(1), 2);
+        assert_eq!(backoff_delay_secs(2), 4);
+        assert_eq!(backoff_delay_secs(3), 8);
+    }
+
+    #[test]
+    fn test_backoff_delay_capped_at_ceiling() {
+        assert_eq!(backoff_delay_secs(20), BACKOFF_CEILING_SECS);
+        assert_eq!(backoff_delay_secs(63), BACKOFF_CEILING_SECS);
+    }
+
+    #[test]
+    fn test_backoff_delay_treats_negative_attempt_as_zero() {
+        assert_eq!(backoff_delay_secs(-5), backoff_delay_secs(0));
+    }
+}
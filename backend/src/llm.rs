@@ -0,0 +1,286 @@
+//! Pluggable chat-completion backend. OpenAI, Ollama, Together, and most
+//! local inference servers (vLLM, LM Studio, ...) all speak the same
+//! `/v1/chat/completions` shape, so `OpenAiCompatibleProvider` implements it
+//! once against a configurable `base_url`/`model`/`api_key`, and
+//! `provider_from_env` picks OpenAI or a self-hosted endpoint based on
+//! config instead of it being baked into the call site.
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Default OpenAI chat-completions endpoint.
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1/chat/completions";
+/// Default OpenAI model, used when `MIXANALYZER_LLM_MODEL` isn't set.
+const DEFAULT_OPENAI_MODEL: &str = "gpt-5";
+
+#[derive(Serialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// Token counts reported by the backend for a single completion, straight
+/// off the response body's `usage` object.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    /// Fold another completion's usage into this one, for callers (like the
+    /// "instructor" retry loop) that make several calls per logical request.
+    pub fn add(&mut self, other: Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// Optional per-1K-token USD pricing for a provider, used to estimate the
+/// cost of a `Usage`. Left unconfigured (`None`) when the price isn't known.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenPricing {
+    pub prompt_usd_per_1k: f64,
+    pub completion_usd_per_1k: f64,
+}
+
+impl TokenPricing {
+    pub fn estimate(&self, usage: Usage) -> f64 {
+        (usage.prompt_tokens as f64 / 1000.0) * self.prompt_usd_per_1k
+            + (usage.completion_tokens as f64 / 1000.0) * self.completion_usd_per_1k
+    }
+}
+
+/// A chat-completion backend. `complete` returns one full text response;
+/// `complete_stream` also forwards token deltas as they arrive.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    async fn complete(&self, messages: &[ChatMessage]) -> Result<(String, Usage), String>;
+
+    /// Same as `complete`, but forwards each token delta to `tx` as it
+    /// arrives off the `text/event-stream` response instead of waiting for
+    /// the full completion, so a caller can render partial output
+    /// progressively. Still returns the full concatenated text (and usage,
+    /// if the backend reports it mid-stream) once the stream ends.
+    async fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        tx: mpsc::UnboundedSender<String>,
+    ) -> Result<(String, Usage), String>;
+
+    /// Per-1K-token USD pricing for this provider, if configured. `None`
+    /// means cost can't be estimated for completions made through it.
+    fn pricing(&self) -> Option<TokenPricing> {
+        None
+    }
+}
+
+/// Talks to any server that implements the OpenAI `/v1/chat/completions`
+/// shape. `api_key` is optional since most local servers don't check one.
+pub struct OpenAiCompatibleProvider {
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    pricing: Option<TokenPricing>,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(
+        base_url: impl Into<String>,
+        model: impl Into<String>,
+        api_key: Option<String>,
+        pricing: Option<TokenPricing>,
+    ) -> Self {
+        Self { base_url: base_url.into(), model: model.into(), api_key, pricing }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatibleProvider {
+    async fn complete(&self, messages: &[ChatMessage]) -> Result<(String, Usage), String> {
+        let client = reqwest::Client::new();
+        let request_body = json!({
+            "model": self.model,
+            "messages": messages,
+            "reasoning_effort": "low",
+            "stream": false
+        });
+
+        let mut req = client.post(&self.base_url).json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            req = req.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let res = req.send().await.map_err(|e| format!("Request failed: {}", e))?;
+
+        if !res.status().is_success() {
+            let error_text = res.text().await.unwrap_or_default();
+            return Err(format!("LLM backend error: {}", error_text));
+        }
+
+        let body: serde_json::Value = res.json().await.map_err(|e| format!("Parse error: {}", e))?;
+
+        let content = body["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or("No content")
+            .to_string();
+        let usage = serde_json::from_value(body["usage"].clone()).unwrap_or_default();
+
+        Ok((content, usage))
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        tx: mpsc::UnboundedSender<String>,
+    ) -> Result<(String, Usage), String> {
+        let client = reqwest::Client::new();
+        let request_body = json!({
+            "model": self.model,
+            "messages": messages,
+            "reasoning_effort": "low",
+            "stream": true,
+            // Asks the backend to emit a final chunk carrying `usage`, the
+            // same `prompt_tokens`/`completion_tokens`/`total_tokens` a
+            // non-streaming call gets back directly.
+            "stream_options": { "include_usage": true }
+        });
+
+        let mut req = client.post(&self.base_url).json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            req = req.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let res = req.send().await.map_err(|e| format!("Request failed: {}", e))?;
+
+        if !res.status().is_success() {
+            let error_text = res.text().await.unwrap_or_default();
+            return Err(format!("LLM backend error: {}", error_text));
+        }
+
+        // The stream is a sequence of `data: {json}\n\n` lines, each carrying
+        // one token in `choices[0].delta.content`, terminated by `data: [DONE]`.
+        // The final chunk before `[DONE]` carries `usage` instead of a delta.
+        let mut full = String::new();
+        let mut usage = Usage::default();
+        let mut buf = String::new();
+        let mut stream = res.bytes_stream();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
+            buf.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some(pos) = buf.find('\n') {
+                let line = buf[..pos].trim_end_matches('\r').to_string();
+                buf.drain(..=pos);
+
+                let Some(data) = line.strip_prefix("data: ") else { continue };
+                if data == "[DONE]" {
+                    return Ok((full, usage));
+                }
+
+                let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else { continue };
+                if let Some(token) = event["choices"][0]["delta"]["content"].as_str() {
+                    full.push_str(token);
+                    let _ = tx.send(token.to_string());
+                }
+                if !event["usage"].is_null() {
+                    usage = serde_json::from_value(event["usage"].clone()).unwrap_or_default();
+                }
+            }
+        }
+
+        Ok((full, usage))
+    }
+
+    fn pricing(&self) -> Option<TokenPricing> {
+        self.pricing
+    }
+}
+
+/// The stock OpenAI backend. A thin wrapper over `OpenAiCompatibleProvider`
+/// pinned to `api.openai.com`, kept as its own type so "use OpenAI" reads
+/// the same way at the call site regardless of how the compatible shape is
+/// implemented underneath.
+pub struct OpenAiProvider {
+    inner: OpenAiCompatibleProvider,
+}
+
+impl OpenAiProvider {
+    pub fn new(api_key: String, model: Option<String>, pricing: Option<TokenPricing>) -> Self {
+        Self {
+            inner: OpenAiCompatibleProvider::new(
+                OPENAI_BASE_URL,
+                model.unwrap_or_else(|| DEFAULT_OPENAI_MODEL.to_string()),
+                Some(api_key),
+                pricing,
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    async fn complete(&self, messages: &[ChatMessage]) -> Result<(String, Usage), String> {
+        self.inner.complete(messages).await
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        tx: mpsc::UnboundedSender<String>,
+    ) -> Result<(String, Usage), String> {
+        self.inner.complete_stream(messages, tx).await
+    }
+
+    fn pricing(&self) -> Option<TokenPricing> {
+        self.inner.pricing()
+    }
+}
+
+/// Reads `MIXANALYZER_LLM_PRICE_PER_1K_PROMPT_USD` and
+/// `MIXANALYZER_LLM_PRICE_PER_1K_COMPLETION_USD`; both must be set and parse
+/// as floats for pricing to be configured, otherwise cost just isn't
+/// estimated for this provider.
+fn pricing_from_env() -> Option<TokenPricing> {
+    let prompt_usd_per_1k = std::env::var("MIXANALYZER_LLM_PRICE_PER_1K_PROMPT_USD").ok()?.parse().ok()?;
+    let completion_usd_per_1k =
+        std::env::var("MIXANALYZER_LLM_PRICE_PER_1K_COMPLETION_USD").ok()?.parse().ok()?;
+    Some(TokenPricing { prompt_usd_per_1k, completion_usd_per_1k })
+}
+
+/// Build the configured provider from environment variables:
+/// - `MIXANALYZER_LLM_BASE_URL` switches to a self-hosted OpenAI-compatible
+///   endpoint (Ollama, Together, vLLM, ...); unset uses OpenAI directly.
+/// - `MIXANALYZER_LLM_MODEL` overrides the model id (defaults to `gpt-5` for
+///   OpenAI; required when `MIXANALYZER_LLM_BASE_URL` is set, since there's
+///   no sane default model for an arbitrary endpoint).
+/// - `OPENAI_API_KEY` is sent as a bearer token when present; local servers
+///   that don't check one can leave it unset.
+/// - `MIXANALYZER_LLM_PRICE_PER_1K_PROMPT_USD` /
+///   `MIXANALYZER_LLM_PRICE_PER_1K_COMPLETION_USD` optionally enable cost
+///   estimation; see `pricing_from_env`.
+pub fn provider_from_env() -> Result<Arc<dyn LlmProvider>, String> {
+    let api_key = std::env::var("OPENAI_API_KEY").ok();
+    let model = std::env::var("MIXANALYZER_LLM_MODEL").ok();
+    let pricing = pricing_from_env();
+
+    if let Ok(base_url) = std::env::var("MIXANALYZER_LLM_BASE_URL") {
+        let model = model.ok_or_else(|| {
+            "MIXANALYZER_LLM_MODEL must be set when MIXANALYZER_LLM_BASE_URL is".to_string()
+        })?;
+        return Ok(Arc::new(OpenAiCompatibleProvider::new(base_url, model, api_key, pricing)));
+    }
+
+    let api_key = api_key.ok_or_else(|| "OPENAI_API_KEY must be set".to_string())?;
+    Ok(Arc::new(OpenAiProvider::new(api_key, model, pricing)))
+}
@@ -17,21 +17,112 @@ use tokio::fs;
 use tower_http::cors::CorsLayer;
 use uuid::Uuid;
 
+mod analysis_cache;
+mod analyzer_server;
 mod audio_analysis;
+mod job_queue;
+mod llm;
+mod mastering_plan;
+mod poll_timer;
 mod projects;
+mod python_env;
+mod stem_metrics;
 mod stem_separation;
-use audio_analysis::{analyze_pair, ComparisonResult};
+mod transcription;
+use audio_analysis::{analyze_pair_cached, check_environment, ComparisonResult, Recommendation};
+use mastering_plan::MasteringPlan;
+use poll_timer::WithPollTimer;
 use stem_separation::StemSeparationResult;
+use transcription::TranscriptSegment;
+
+/// `job_queue.queue` name used for background stem separation.
+const STEM_SEPARATION_QUEUE: &str = "stem_separation";
+/// `job_queue.queue` name used for the main analysis pipeline.
+const ANALYSIS_QUEUE: &str = "analysis";
+
+/// Payload persisted in `job_queue.job` for a stem-separation job. The row's
+/// id doubles as the `stem_job_id` used everywhere else in the app. `status`
+/// is the full live/terminal state, so a reconnecting client (or the status
+/// endpoints) can read it straight back out of the table.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StemSeparationJob {
+    mix_path: PathBuf,
+    ref_path: PathBuf,
+    status: StemJobStatus,
+}
+
+/// Payload persisted in `job_queue.job` for the main analysis job.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct AnalysisJob {
+    mix_path: PathBuf,
+    ref_path: PathBuf,
+    project_id: Option<Uuid>,
+    version_name: Option<String>,
+    stem_job_id: Option<String>,
+    /// SHA-256 of the mix+reference file bytes, used to dedupe against
+    /// `analyses.content_hash` so an unchanged pair doesn't pay for a fresh
+    /// run. See `analysis_cache`.
+    content_hash: Option<String>,
+    status: JobStatus,
+}
 
 // --- Data Structures ---
 
+/// Crate-wide response envelope. Handlers return `Success` for the happy
+/// path, `Failure` for expected conditions (not found, validation, an
+/// orphaned reference), and `Fatal` only for genuine DB/infra errors. The
+/// frontend can `switch` on `type` instead of guessing from raw rows.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum ApiResponse<T> {
+    Success { content: T },
+    Failure { content: String },
+    Fatal { content: String },
+}
+
+impl<T> ApiResponse<T> {
+    pub fn success(content: T) -> Json<Self> {
+        Json(ApiResponse::Success { content })
+    }
+
+    pub fn failure(message: impl Into<String>) -> Json<Self> {
+        Json(ApiResponse::Failure { content: message.into() })
+    }
+
+    pub fn fatal(message: impl Into<String>) -> Json<Self> {
+        Json(ApiResponse::Fatal { content: message.into() })
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "status", content = "data")]
 pub enum JobStatus {
     Queued,
-    Processing(String), // Current step description
-    Completed(ComparisonResult, String), // Result + AI Text
+    /// `attempts` is the current attempt number (1 on the first try), so the
+    /// frontend can show e.g. "retry 2/3".
+    Processing { message: String, attempts: i32 },
+    /// The AI mastering advice streaming in token-by-token; `partial` is the
+    /// text generated so far, so a reconnecting client can render it
+    /// immediately without waiting for `Completed`.
+    Streaming { partial: String, attempts: i32 },
+    /// The last element is the prioritized mixing-advice checklist derived
+    /// from `ComparisonResult::recommendations` — computed once here rather
+    /// than making the frontend re-diff the metrics itself.
+    Completed(ComparisonResult, MasteringPlan, AiUsage, Vec<Recommendation>),
     Failed(String),
+    /// Terminal: the job's queued payload couldn't be deserialized at all, so
+    /// retrying it would never help. See `job_queue::dead_letter`.
+    InvalidJob { error: String, raw: serde_json::Value },
+}
+
+/// Token usage and estimated cost for the AI completion(s) a job made,
+/// summed across any "instructor" retries (see `mastering_plan`).
+/// `estimated_cost_usd` is `None` when no per-1K-token price is configured
+/// for the active `LlmProvider`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AiUsage {
+    pub usage: llm::Usage,
+    pub estimated_cost_usd: Option<f64>,
 }
 
 /// Job status for stem separation (separate from main analysis)
@@ -39,16 +130,26 @@ pub enum JobStatus {
 #[serde(tag = "status", content = "data")]
 pub enum StemJobStatus {
     Queued,
-    Separating { progress: u8, stage: String },
+    Separating { progress: u8, stage: String, attempts: i32 },
     Analyzing { stem: String },
+    /// Running speech-to-text over the vocal stem. See `transcription`.
+    Transcribing { stem: String },
     Completed(StemAnalysisResult),
     Failed(String),
+    /// Terminal: the job's queued payload couldn't be deserialized at all, so
+    /// retrying it would never help. See `job_queue::dead_letter`.
+    InvalidJob { error: String, raw: serde_json::Value },
 }
 
 /// Result of stem-level analysis
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StemAnalysisResult {
     pub stems: std::collections::HashMap<String, StemMetrics>,
+    /// Timestamped lyric segments transcribed from the vocal stem, if one was
+    /// present and transcription succeeded. `None` rather than a job failure
+    /// when there's no vocal stem or the transcription pass couldn't run, so
+    /// a missing transcript never sinks an otherwise-complete analysis.
+    pub vocal_transcript: Option<Vec<TranscriptSegment>>,
 }
 
 /// Metrics for a single stem
@@ -63,21 +164,67 @@ pub struct StemMetrics {
 #[derive(Clone)]
 struct AppState {
     upload_dir: String,
-    openai_api_key: String,
-    jobs: Arc<Mutex<HashMap<String, JobStatus>>>,
-    stem_jobs: Arc<Mutex<HashMap<String, StemJobStatus>>>,
+    /// The configured chat-completion backend (OpenAI or a self-hosted
+    /// OpenAI-compatible endpoint). See `llm::provider_from_env`.
+    llm: std::sync::Arc<dyn llm::LlmProvider>,
+    /// The configured speech-to-text backend for vocal-stem lyric
+    /// transcription (local Whisper or a remote OpenAI-compatible
+    /// endpoint). See `transcription::provider_from_env`.
+    transcription: std::sync::Arc<dyn transcription::TranscriptionProvider>,
+    /// Live stem-separation progress, broadcast to any client subscribed via
+    /// `GET /api/versions/:id/stem-progress`. Keyed by stem_job_id, i.e. the
+    /// stem job's `job_queue` row id as a string. Status itself lives in
+    /// `job_queue`, not here — this is only a fan-out for push notifications.
+    stem_progress: Arc<Mutex<HashMap<String, tokio::sync::broadcast::Sender<StemProgressEvent>>>>,
+    /// Latest `JobStatus` per analysis job, created when the job is enqueued.
+    /// `GET /api/jobs/:id` subscribes to the watch channel directly instead
+    /// of polling `job_queue`, so status transitions are pushed the instant
+    /// they happen.
+    job_status: Arc<Mutex<HashMap<Uuid, tokio::sync::watch::Sender<JobStatus>>>>,
+    /// Same idea as `job_status`, for `GET /api/stems/:id`.
+    stem_job_status: Arc<Mutex<HashMap<Uuid, tokio::sync::watch::Sender<StemJobStatus>>>>,
     db: sqlx::PgPool,
 }
 
-#[derive(Serialize)]
-struct JobResponse {
-    job_id: String,
+/// A single event pushed over the stem-progress SSE stream: either a
+/// progress tick, or a terminal event carrying the final result.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+enum StemProgressEvent {
+    Progress { progress: u8, stage: String, attempts: i32 },
+    Done { result: StemJobStatus },
 }
 
-#[derive(Serialize)]
-struct FullJobResponse {
-    job_id: String,
-    stem_job_id: String,
+/// Get (or lazily create) the broadcast channel a client can subscribe to
+/// for a given stem job's progress.
+fn get_or_create_stem_channel(
+    state: &AppState,
+    stem_job_id: &str,
+) -> tokio::sync::broadcast::Sender<StemProgressEvent> {
+    let mut channels = state.stem_progress.lock().unwrap();
+    channels
+        .entry(stem_job_id.to_string())
+        .or_insert_with(|| tokio::sync::broadcast::channel(64).0)
+        .clone()
+}
+
+/// Push a status transition into an analysis job's watch channel, if one is
+/// registered (it's created in `enqueue_analysis`). A missing channel just
+/// means no client can be subscribed to it yet, so there's nothing to do.
+fn send_job_status(state: &AppState, job_id: Uuid, status: &JobStatus) {
+    let channels = state.job_status.lock().unwrap();
+    if let Some(tx) = channels.get(&job_id) {
+        let _ = tx.send(status.clone());
+    }
+}
+
+/// Push a status transition into a stem job's watch channel, if one is
+/// registered (it's created in `enqueue_stem_separation`).
+fn send_stem_job_status(state: &AppState, stem_job_id: Uuid, status: &StemJobStatus) {
+    let channels = state.stem_job_status.lock().unwrap();
+    if let Some(tx) = channels.get(&stem_job_id) {
+        let _ = tx.send(status.clone());
+    }
 }
 
 // --- Main ---
@@ -87,7 +234,8 @@ async fn main() {
     // Load environment variables
     dotenvy::dotenv().ok();
     let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string());
-    let openai_api_key = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
+    let llm_provider = llm::provider_from_env().expect("Failed to configure LLM backend");
+    let transcription_provider = transcription::provider_from_env();
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
     // Create upload directory if it doesn't exist
@@ -110,18 +258,29 @@ async fn main() {
     let upload_dir_for_serve = upload_dir.clone();
     let state = AppState {
         upload_dir,
-        openai_api_key,
-        jobs: Arc::new(Mutex::new(HashMap::new())),
-        stem_jobs: Arc::new(Mutex::new(HashMap::new())),
+        llm: llm_provider,
+        transcription: transcription_provider,
+        stem_progress: Arc::new(Mutex::new(HashMap::new())),
+        job_status: Arc::new(Mutex::new(HashMap::new())),
+        stem_job_status: Arc::new(Mutex::new(HashMap::new())),
         db: pool,
     };
 
+    // Background workers that drain the durable job queues, plus a reaper
+    // that requeues jobs abandoned by a crashed worker.
+    tokio::spawn(analysis_worker(state.clone()));
+    tokio::spawn(stem_separation_worker(state.clone()));
+    tokio::spawn(job_queue_reaper(state.clone()));
+
     // Router
     let app = Router::new()
         .route("/", get(root_handler))
+        .route("/api/doctor", get(doctor))
         .route("/api/analyze", post(start_analysis_job))
         .route("/api/jobs/:id", get(job_status_stream))
         .route("/api/stems/:id", get(stem_job_status_stream))
+        .route("/api/stem-jobs/:id/status", get(projects::get_stem_job_queue_status))
+        .route("/api/versions/:id/stem-progress", get(version_stem_progress_stream))
         .route("/api/projects", get(projects::list_projects).post(projects::create_project))
         .route("/api/projects/:id", get(projects::get_project))
         .route("/api/analyses/version/:id", get(projects::get_analysis_by_version))
@@ -139,7 +298,14 @@ async fn main() {
     let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
     println!("Server running on http://{}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()).await.unwrap();
+
+    // Stop the persistent analyzer worker rather than leaving it orphaned.
+    analyzer_server::shutdown();
+}
+
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
 }
 
 async fn root_handler() -> Html<&'static str> {
@@ -148,32 +314,33 @@ async fn root_handler() -> Html<&'static str> {
 
 // --- Handlers ---
 
+/// Preflight check of the analysis environment (interpreter + required
+/// Python packages), so the frontend can show a clear "install X" message
+/// before a user uploads a file and hits the same failure inside a job.
+async fn doctor() -> impl IntoResponse {
+    match tokio::task::spawn_blocking(check_environment).await {
+        Ok(Ok(report)) => ApiResponse::success(report),
+        Ok(Err(e)) => ApiResponse::failure(e),
+        Err(e) => ApiResponse::fatal(format!("Task panic: {}", e)),
+    }
+}
+
 async fn start_analysis_job(
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> impl IntoResponse {
-    let job_id = Uuid::new_v4().to_string();
-    let stem_job_id = Uuid::new_v4().to_string();
-    
-    // Initialize both job statuses
-    {
-        let mut jobs = state.jobs.lock().unwrap();
-        jobs.insert(job_id.clone(), JobStatus::Queued);
-    }
-    {
-        let mut stem_jobs = state.stem_jobs.lock().unwrap();
-        stem_jobs.insert(stem_job_id.clone(), StemJobStatus::Queued);
-    }
-
     // Handle file uploads and fields
     let mut mix_path = None;
     let mut ref_path = None;
+    let mut mix_bytes = None;
+    let mut ref_bytes = None;
     let mut project_id: Option<Uuid> = None;
     let mut version_name: Option<String> = None;
+    let mut force = false;
 
     while let Some(field) = multipart.next_field().await.unwrap_or(None) {
         let name = field.name().unwrap_or("").to_string();
-        
+
         if name == "project_id" {
             if let Ok(val) = field.text().await {
                 if let Ok(uuid) = Uuid::parse_str(&val) {
@@ -182,7 +349,7 @@ async fn start_analysis_job(
             }
             continue;
         }
-        
+
         if name == "version_name" {
             if let Ok(val) = field.text().await {
                 version_name = Some(val);
@@ -190,62 +357,98 @@ async fn start_analysis_job(
             continue;
         }
 
+        if name == "force" {
+            if let Ok(val) = field.text().await {
+                force = val == "true";
+            }
+            continue;
+        }
+
         let file_name = field.file_name().map(|f| f.to_string());
 
         if let Some(file_name) = file_name {
             if let Ok(data) = field.bytes().await {
                 let dest_path = PathBuf::from(&state.upload_dir).join(format!("{}_{}", Uuid::new_v4(), file_name));
-                if let Ok(_) = fs::write(&dest_path, data).await {
+                if let Ok(_) = fs::write(&dest_path, &data).await {
                     if name == "mix" {
                         mix_path = Some(dest_path);
+                        mix_bytes = Some(data);
                     } else if name == "reference" {
                         ref_path = Some(dest_path);
+                        ref_bytes = Some(data);
                     }
                 }
             }
         }
     }
 
-    if mix_path.is_none() || ref_path.is_none() {
-        let mut jobs = state.jobs.lock().unwrap();
-        jobs.insert(job_id.clone(), JobStatus::Failed("Missing mix or reference file".to_string()));
-        let mut stem_jobs = state.stem_jobs.lock().unwrap();
-        stem_jobs.insert(stem_job_id.clone(), StemJobStatus::Failed("Missing files".to_string()));
-        return Json(FullJobResponse { job_id, stem_job_id });
-    }
-
-    let mix_path = mix_path.unwrap();
-    let ref_path = ref_path.unwrap();
-
-    // Spawn background task for main analysis
-    let state_clone = state.clone();
-    let job_id_clone = job_id.clone();
-    let mix_path_clone = mix_path.clone();
-    let ref_path_clone = ref_path.clone();
-    let stem_job_id_clone = stem_job_id.clone();
+    let (Some(mix_path), Some(ref_path)) = (mix_path, ref_path) else {
+        return Json(json!({ "error": "Missing mix or reference file" }));
+    };
+    let (Some(mix_bytes), Some(ref_bytes)) = (mix_bytes, ref_bytes) else {
+        return Json(json!({ "error": "Missing mix or reference file" }));
+    };
 
-    tokio::spawn(async move {
-        process_analysis(state_clone, job_id_clone, mix_path_clone, ref_path_clone, project_id, version_name, Some(stem_job_id_clone)).await;
-    });
+    let hash = analysis_cache::content_hash(&mix_bytes, &ref_bytes);
+
+    if !force {
+        match analysis_cache::find(&state.db, &hash).await {
+            Ok(Some(cached)) => {
+                let stem_job_id = cached.stem_job_id.clone();
+                return match enqueue_cached_analysis(
+                    &state, &mix_path, &ref_path, project_id, version_name, stem_job_id.clone(), hash, cached,
+                )
+                .await
+                {
+                    Ok(job_id) => Json(json!({ "job_id": job_id, "stem_job_id": stem_job_id, "cached": true })),
+                    Err(e) => Json(json!({ "error": e })),
+                };
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("[analysis_cache] lookup failed: {}", e),
+        }
+    }
 
-    // Spawn background task for stem separation (runs in parallel!)
-    let state_clone2 = state.clone();
-    let stem_job_id_clone = stem_job_id.clone();
-    let mix_path_for_stems = mix_path.clone();
-    let ref_path_for_stems = ref_path.clone();
+    // Enqueue stem separation first so the main analysis job can carry its
+    // id along and link the two once both finish.
+    let stem_job_id = match enqueue_stem_separation(&state, &mix_path, &ref_path).await {
+        Ok(id) => id,
+        Err(e) => return Json(json!({ "error": e })),
+    };
 
-    tokio::spawn(async move {
-        process_stem_separation(state_clone2, stem_job_id_clone, mix_path_for_stems, ref_path_for_stems).await;
-    });
+    let job_id = match enqueue_analysis(
+        &state,
+        &mix_path,
+        &ref_path,
+        project_id,
+        version_name,
+        Some(stem_job_id.clone()),
+        Some(hash),
+    )
+    .await
+    {
+        Ok(id) => id,
+        Err(e) => return Json(json!({ "error": e })),
+    };
 
-    Json(FullJobResponse { job_id, stem_job_id })
+    Json(json!({ "job_id": job_id, "stem_job_id": stem_job_id }))
 }
 
 /// Re-analyze a version using stored file paths
 async fn reanalyze_version(
     State(state): State<AppState>,
     Path(version_id): Path<Uuid>,
+    mut multipart: Multipart,
 ) -> Json<serde_json::Value> {
+    let mut force = false;
+    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+        if field.name().unwrap_or("") == "force" {
+            if let Ok(val) = field.text().await {
+                force = val == "true";
+            }
+        }
+    }
+
     // Get file paths from database
     let version = sqlx::query!(
         "SELECT mv.file_path as mix_path, mv.project_id, rt.file_path as ref_path 
@@ -276,37 +479,41 @@ async fn reanalyze_version(
         return Json(json!({ "error": "Reference file not found on disk" }));
     }
 
-    // Create new job IDs
-    let job_id = Uuid::new_v4().to_string();
-    let stem_job_id = Uuid::new_v4().to_string();
-
-    // Initialize job statuses
-    {
-        let mut jobs = state.jobs.lock().unwrap();
-        jobs.insert(job_id.clone(), JobStatus::Queued);
-    }
-    {
-        let mut stem_jobs = state.stem_jobs.lock().unwrap();
-        stem_jobs.insert(stem_job_id.clone(), StemJobStatus::Queued);
+    let (mix_bytes, ref_bytes) = match tokio::try_join!(fs::read(&mix_path), fs::read(&ref_path)) {
+        Ok(bytes) => bytes,
+        Err(e) => return Json(json!({ "error": format!("Failed to read files: {}", e) })),
+    };
+    let hash = analysis_cache::content_hash(&mix_bytes, &ref_bytes);
+
+    if !force {
+        match analysis_cache::find(&state.db, &hash).await {
+            Ok(Some(cached)) => {
+                let stem_job_id = cached.stem_job_id.clone();
+                return match enqueue_cached_analysis(
+                    &state, &mix_path, &ref_path, None, None, stem_job_id.clone(), hash, cached,
+                )
+                .await
+                {
+                    Ok(job_id) => Json(json!({ "job_id": job_id, "stem_job_id": stem_job_id, "cached": true })),
+                    Err(e) => Json(json!({ "error": e })),
+                };
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("[analysis_cache] lookup failed: {}", e),
+        }
     }
 
-    // Spawn background task for main analysis (no project_id/version_name - we're re-analyzing existing version)
-    let state_clone = state.clone();
-    let job_id_clone = job_id.clone();
-    let mix_path_clone = mix_path.clone();
-    let ref_path_clone = ref_path.clone();
-
-    tokio::spawn(async move {
-        process_analysis(state_clone, job_id_clone, mix_path_clone, ref_path_clone, None, None, None).await;
-    });
-
-    // Spawn background task for stem separation
-    let state_clone2 = state.clone();
-    let stem_job_id_clone = stem_job_id.clone();
+    // Enqueue stem separation first (no project_id/version_name - we're
+    // re-analyzing an existing version, so the main job doesn't persist one).
+    let stem_job_id = match enqueue_stem_separation(&state, &mix_path, &ref_path).await {
+        Ok(id) => id,
+        Err(e) => return Json(json!({ "error": e })),
+    };
 
-    tokio::spawn(async move {
-        process_stem_separation(state_clone2, stem_job_id_clone, mix_path, ref_path).await;
-    });
+    let job_id = match enqueue_analysis(&state, &mix_path, &ref_path, None, None, None, Some(hash)).await {
+        Ok(id) => id,
+        Err(e) => return Json(json!({ "error": e })),
+    };
 
     Json(json!({
         "job_id": job_id,
@@ -349,14 +556,10 @@ async fn reanalyze_stems_only(
         return Json(json!({ "error": "Reference file not found on disk" }));
     }
 
-    // Create new stem job ID only
-    let stem_job_id = Uuid::new_v4().to_string();
-
-    // Initialize stem job status
-    {
-        let mut stem_jobs = state.stem_jobs.lock().unwrap();
-        stem_jobs.insert(stem_job_id.clone(), StemJobStatus::Queued);
-    }
+    let stem_job_id = match enqueue_stem_separation(&state, &mix_path, &ref_path).await {
+        Ok(id) => id,
+        Err(e) => return Json(json!({ "error": e })),
+    };
 
     // Save stem_job_id to database
     let _ = sqlx::query!(
@@ -367,140 +570,330 @@ async fn reanalyze_stems_only(
     .execute(&state.db)
     .await;
 
-    // Spawn background task for stem separation only
-    let state_clone = state.clone();
-    let stem_job_id_clone = stem_job_id.clone();
-
-    tokio::spawn(async move {
-        process_stem_separation(state_clone, stem_job_id_clone, mix_path, ref_path).await;
-    });
-
     Json(json!({
         "stem_job_id": stem_job_id
     }))
 }
 
-async fn process_analysis(
-    state: AppState,
-    job_id: String,
-    mix_path: PathBuf,
-    ref_path: PathBuf,
+/// Insert an analysis job onto the durable queue, returning its id (used as
+/// the `job_id` everywhere else). The background worker spawned in `main`
+/// claims and runs it.
+async fn enqueue_analysis(
+    state: &AppState,
+    mix_path: &std::path::Path,
+    ref_path: &std::path::Path,
+    project_id: Option<Uuid>,
+    version_name: Option<String>,
+    stem_job_id: Option<String>,
+    content_hash: Option<String>,
+) -> Result<String, String> {
+    let job = serde_json::to_value(AnalysisJob {
+        mix_path: mix_path.to_path_buf(),
+        ref_path: ref_path.to_path_buf(),
+        project_id,
+        version_name,
+        stem_job_id,
+        content_hash,
+        status: JobStatus::Queued,
+    })
+    .map_err(|e| format!("Failed to serialize analysis job: {}", e))?;
+
+    let id = job_queue::enqueue(&state.db, ANALYSIS_QUEUE, &job).await?;
+
+    // Register the watch channel before returning, so a client that
+    // subscribes immediately after this call never races the worker.
+    state
+        .job_status
+        .lock()
+        .unwrap()
+        .insert(id, tokio::sync::watch::channel(JobStatus::Queued).0);
+
+    Ok(id.to_string())
+}
+
+/// Short-circuit for a content-hash cache hit: instead of going through the
+/// queue/worker, the job_queue row is created already `completed` and the
+/// watch channel is pre-populated with the final status, so the client sees
+/// a `Completed` job immediately with no Essentia/Demucs/OpenAI work done.
+/// Still persists a fresh `analyses` row (if `project_id` is set) so the
+/// version history shows this as its own entry, just re-using the cached
+/// metrics and AI report.
+async fn enqueue_cached_analysis(
+    state: &AppState,
+    mix_path: &std::path::Path,
+    ref_path: &std::path::Path,
     project_id: Option<Uuid>,
     version_name: Option<String>,
     stem_job_id: Option<String>,
-) {
-    // Update: Running Essentia
-    update_job_status(&state, &job_id, JobStatus::Processing("Running Essentia Analysis...".to_string()));
+    content_hash: String,
+    cached: analysis_cache::CachedAnalysis,
+) -> Result<String, String> {
+    let metrics: ComparisonResult = serde_json::from_value(cached.metrics)
+        .map_err(|e| format!("Failed to parse cached metrics: {}", e))?;
+    let plan: MasteringPlan = serde_json::from_str(&cached.ai_report)
+        .map_err(|e| format!("Failed to parse cached mastering plan: {}", e))?;
+    // No AI call is made on a cache hit, so usage (and therefore cost) is zero.
+    let ai_usage = AiUsage {
+        usage: llm::Usage::default(),
+        estimated_cost_usd: state.llm.pricing().map(|p| p.estimate(llm::Usage::default())),
+    };
+
+    let job = AnalysisJob {
+        mix_path: mix_path.to_path_buf(),
+        ref_path: ref_path.to_path_buf(),
+        project_id,
+        version_name,
+        stem_job_id,
+        content_hash: Some(content_hash),
+        status: JobStatus::Completed(metrics.clone(), plan, ai_usage, metrics.recommendations()),
+    };
+
+    let job_value = serde_json::to_value(&job).map_err(|e| format!("Failed to serialize analysis job: {}", e))?;
+    // Inserted directly as `completed` so the analysis worker never sees it
+    // as claimable.
+    let id = sqlx::query!(
+        "INSERT INTO job_queue (queue, job, status) VALUES ($1, $2, 'completed') RETURNING id",
+        ANALYSIS_QUEUE,
+        &job_value
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| format!("Failed to enqueue cached analysis job: {}", e))?
+    .id;
+
+    state
+        .job_status
+        .lock()
+        .unwrap()
+        .insert(id, tokio::sync::watch::channel(job.status.clone()).0);
+
+    if let Some(pid) = job.project_id {
+        let v_name = job.version_name.clone().unwrap_or_else(|| "New Version".to_string());
+
+        let mix_version_id = sqlx::query!(
+            "INSERT INTO mix_versions (project_id, version_name, file_path, stem_job_id) VALUES ($1, $2, $3, $4) RETURNING id",
+            pid,
+            v_name,
+            job.mix_path.to_string_lossy().to_string(),
+            job.stem_job_id
+        )
+        .fetch_one(&state.db)
+        .await;
+
+        let ref_track_id = sqlx::query!(
+            "INSERT INTO reference_tracks (project_id, name, file_path) VALUES ($1, $2, $3) RETURNING id",
+            pid,
+            "Reference Track",
+            job.ref_path.to_string_lossy().to_string()
+        )
+        .fetch_one(&state.db)
+        .await;
+
+        if let (Ok(mv), Ok(rt)) = (mix_version_id, ref_track_id) {
+            let _ = sqlx::query!(
+                "INSERT INTO analyses (mix_version_id, reference_track_id, metrics, ai_report, content_hash) VALUES ($1, $2, $3, $4, $5)",
+                mv.id,
+                rt.id,
+                sqlx::types::Json(&metrics) as _,
+                cached.ai_report,
+                job.content_hash
+            )
+            .execute(&state.db)
+            .await;
+        }
+    }
+
+    Ok(id.to_string())
+}
+
+/// Persist a status transition into the job's `job_queue` row and push it
+/// into the job's watch channel for any subscribed SSE client. Always
+/// re-serializes the full `AnalysisJob`, carrying its paths and metadata
+/// along, so a crash-reclaim via the reaper has everything it needs to rerun
+/// the job from scratch.
+async fn report_job_progress(state: &AppState, job_queue_id: Uuid, job: &AnalysisJob) {
+    let value = serde_json::to_value(job).unwrap_or_default();
+    if let Err(e) = job_queue::update_progress(&state.db, job_queue_id, &value).await {
+        eprintln!("[job_queue] failed to persist analysis progress: {}", e);
+    }
+    send_job_status(state, job_queue_id, &job.status);
+}
+
+/// Run one analysis job to completion, reporting progress into `job_queue`
+/// as it goes. `attempts` is the current attempt number (1 on the first
+/// try), surfaced in the `Processing` status so the frontend can show
+/// "retry 2/3". Returns the final `JobStatus`; the caller is responsible for
+/// marking the `job_queue` row completed or failed.
+async fn process_analysis(state: AppState, job_queue_id: Uuid, mut job: AnalysisJob, attempts: i32) -> JobStatus {
+    job.status = JobStatus::Processing { message: "Running Essentia Analysis...".to_string(), attempts };
+    report_job_progress(&state, job_queue_id, &job).await;
 
     // Run Analysis
     let analysis_result = tokio::task::spawn_blocking({
-        let mix = mix_path.clone();
-        let ref_p = ref_path.clone();
-        move || analyze_pair(&mix, &ref_p)
-    }).await.unwrap();
-
-    match analysis_result {
-        Ok(metrics) => {
-            // Update: Generating AI Report
-            update_job_status(&state, &job_id, JobStatus::Processing("Consulting AI Expert...".to_string()));
-
-            // Call OpenAI
-            match request_ai_completion(&state.openai_api_key, &metrics).await {
-                Ok(ai_text) => {
-                    // Persist if project_id is present
-                    if let Some(pid) = project_id {
-                        let v_name = version_name.unwrap_or_else(|| "New Version".to_string());
-                        
-                        // 1. Save Mix Version
-                        let mix_version_id = sqlx::query!(
-                            "INSERT INTO mix_versions (project_id, version_name, file_path, stem_job_id) VALUES ($1, $2, $3, $4) RETURNING id",
-                            pid,
-                            v_name,
-                            mix_path.to_string_lossy().to_string(),
-                            stem_job_id
-                        )
-                        .fetch_one(&state.db)
-                        .await;
-
-                        // 2. Save Reference Track (Simplified: always create new for now)
-                        let ref_track_id = sqlx::query!(
-                            "INSERT INTO reference_tracks (project_id, name, file_path) VALUES ($1, $2, $3) RETURNING id",
-                            pid,
-                            "Reference Track", // Could extract filename if passed
-                            ref_path.to_string_lossy().to_string()
-                        )
-                        .fetch_one(&state.db)
-                        .await;
-
-                        if let (Ok(mv), Ok(rt)) = (mix_version_id, ref_track_id) {
-                            // 3. Save Analysis
-                            let _ = sqlx::query!(
-                                "INSERT INTO analyses (mix_version_id, reference_track_id, metrics, ai_report) VALUES ($1, $2, $3, $4)",
-                                mv.id,
-                                rt.id,
-                                sqlx::types::Json(&metrics) as _,
-                                ai_text
-                            )
-                            .execute(&state.db)
-                            .await;
-                        }
-                    }
+        let mix = job.mix_path.clone();
+        let ref_p = job.ref_path.clone();
+        move || analyze_pair_cached(&mix, &ref_p)
+    })
+    .with_poll_timer("essentia_analysis")
+    .await;
 
-                    update_job_status(&state, &job_id, JobStatus::Completed(metrics, ai_text));
-                }
-                Err(e) => {
-                    update_job_status(&state, &job_id, JobStatus::Failed(format!("AI Error: {}", e)));
-                }
-            }
+    let metrics = match analysis_result {
+        Ok(Ok(metrics)) => metrics,
+        Ok(Err(e)) => return JobStatus::Failed(format!("Analysis Error: {}", e)),
+        Err(e) => return JobStatus::Failed(format!("Task panic: {}", e)),
+    };
+
+    job.status = JobStatus::Processing { message: "Consulting AI Expert...".to_string(), attempts };
+    report_job_progress(&state, job_queue_id, &job).await;
+
+    // Forward streamed tokens straight into the job's watch channel as they
+    // arrive, so a subscribed client renders the advice progressively
+    // instead of freezing until the whole completion is ready. Only the
+    // final `Completed` status is persisted to `job_queue` — intermediate
+    // partials are cheap, in-memory pushes.
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let forward_state = state.clone();
+    let forward_task = tokio::spawn(async move {
+        let mut partial = String::new();
+        while let Some(token) = rx.recv().await {
+            partial.push_str(&token);
+            send_job_status(&forward_state, job_queue_id, &JobStatus::Streaming { partial: partial.clone(), attempts });
         }
+    });
+
+    let (plan, usage) = match mastering_plan::request_mastering_plan(&*state.llm, &metrics, tx)
+        .with_poll_timer("ai_completion")
+        .await
+    {
+        Ok(result) => result,
         Err(e) => {
-            update_job_status(&state, &job_id, JobStatus::Failed(format!("Analysis Error: {}", e)));
+            forward_task.abort();
+            return JobStatus::Failed(format!("AI Error: {}", e));
+        }
+    };
+    let _ = forward_task.await;
+
+    let ai_usage = AiUsage { estimated_cost_usd: state.llm.pricing().map(|p| p.estimate(usage)), usage };
+    let plan_json = serde_json::to_string(&plan).unwrap_or_default();
+
+    // Persist if project_id is present
+    if let Some(pid) = job.project_id {
+        let v_name = job.version_name.clone().unwrap_or_else(|| "New Version".to_string());
+
+        // 1. Save Mix Version
+        let mix_version_id = sqlx::query!(
+            "INSERT INTO mix_versions (project_id, version_name, file_path, stem_job_id) VALUES ($1, $2, $3, $4) RETURNING id",
+            pid,
+            v_name,
+            job.mix_path.to_string_lossy().to_string(),
+            job.stem_job_id
+        )
+        .fetch_one(&state.db)
+        .await;
+
+        // 2. Save Reference Track (Simplified: always create new for now)
+        let ref_track_id = sqlx::query!(
+            "INSERT INTO reference_tracks (project_id, name, file_path) VALUES ($1, $2, $3) RETURNING id",
+            pid,
+            "Reference Track", // Could extract filename if passed
+            job.ref_path.to_string_lossy().to_string()
+        )
+        .fetch_one(&state.db)
+        .await;
+
+        if let (Ok(mv), Ok(rt)) = (mix_version_id, ref_track_id) {
+            // 3. Save Analysis
+            let _ = sqlx::query!(
+                "INSERT INTO analyses (mix_version_id, reference_track_id, metrics, ai_report, content_hash) VALUES ($1, $2, $3, $4, $5)",
+                mv.id,
+                rt.id,
+                sqlx::types::Json(&metrics) as _,
+                plan_json,
+                job.content_hash
+            )
+            .execute(&state.db)
+            .await;
         }
     }
+
+    let recommendations = metrics.recommendations();
+    JobStatus::Completed(metrics, plan, ai_usage, recommendations)
 }
 
-fn update_job_status(state: &AppState, job_id: &str, status: JobStatus) {
-    let mut jobs = state.jobs.lock().unwrap();
-    jobs.insert(job_id.to_string(), status);
+/// Background loop that claims queued analysis jobs one at a time and runs
+/// them to completion, retrying on failure via `job_queue::fail_or_retry`.
+async fn analysis_worker(state: AppState) {
+    loop {
+        match job_queue::claim_job(&state.db, ANALYSIS_QUEUE).await {
+            Ok(Some(row)) => {
+                let raw_job = row.job.clone();
+                let job: AnalysisJob = match serde_json::from_value(row.job) {
+                    Ok(job) => job,
+                    Err(e) => {
+                        eprintln!("[job_queue] dead-lettering malformed analysis job {}: {}", row.id, e);
+                        let _ = job_queue::dead_letter(&state.db, row.id, &raw_job, &e.to_string()).await;
+                        send_job_status(&state, row.id, &JobStatus::InvalidJob { error: e.to_string(), raw: raw_job });
+                        continue;
+                    }
+                };
+
+                let status = process_analysis(state.clone(), row.id, job.clone(), row.retries + 1).await;
+
+                match &status {
+                    JobStatus::Failed(e) => {
+                        // Only push a terminal status once the job is out of
+                        // retries; mid-retry, the next attempt's Processing
+                        // updates supersede this one.
+                        match job_queue::fail_or_retry(&state.db, row.id, e).await {
+                            Ok(true) => send_job_status(&state, row.id, &status),
+                            Ok(false) => {}
+                            Err(db_err) => eprintln!("[job_queue] failed to requeue job {}: {}", row.id, db_err),
+                        }
+                    }
+                    _ => {
+                        let final_job = serde_json::to_value(AnalysisJob { status: status.clone(), ..job }).unwrap_or_default();
+                        if let Err(e) = job_queue::complete_job(&state.db, row.id, &final_job).await {
+                            eprintln!("[job_queue] failed to mark job {} completed: {}", row.id, e);
+                        }
+                        send_job_status(&state, row.id, &status);
+                    }
+                }
+            }
+            Ok(None) => {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Err(e) => {
+                eprintln!("[job_queue] failed to claim analysis job: {}", e);
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
 }
 
 async fn job_status_stream(
-    Path(job_id): Path<String>,
+    Path(job_id): Path<Uuid>,
     State(state): State<AppState>,
 ) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let rx = state.job_status.lock().unwrap().get(&job_id).map(|tx| tx.subscribe());
+
     let stream = async_stream::stream! {
-        let mut last_status_json = String::new();
+        let Some(mut rx) = rx else {
+            yield Ok(Event::default().data(json!({ "status": "Failed", "data": "Job not found" }).to_string()));
+            return;
+        };
 
         loop {
-            let status = {
-                let jobs = state.jobs.lock().unwrap();
-                jobs.get(&job_id).cloned()
-            };
-
-            match status {
-                Some(status) => {
-                    let json = serde_json::to_string(&status).unwrap();
-                    
-                    // Only send if status changed
-                    if json != last_status_json {
-                        yield Ok(Event::default().data(&json));
-                        last_status_json = json;
-                    }
+            let status = rx.borrow_and_update().clone();
+            yield Ok(Event::default().data(serde_json::to_string(&status).unwrap()));
 
-                    match status {
-                        JobStatus::Completed(_, _) | JobStatus::Failed(_) => {
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
-                None => {
-                    yield Ok(Event::default().data(json!({ "status": "Failed", "data": "Job not found" }).to_string()));
-                    break;
-                }
+            if matches!(status, JobStatus::Completed(_, _, _, _) | JobStatus::Failed(_) | JobStatus::InvalidJob { .. }) {
+                break;
             }
 
-            tokio::time::sleep(Duration::from_millis(500)).await;
+            if rx.changed().await.is_err() {
+                // Sender dropped (shouldn't happen while the server is up).
+                break;
+            }
         }
     };
 
@@ -510,72 +903,238 @@ async fn job_status_stream(
 // --- Stem Separation SSE Stream ---
 
 async fn stem_job_status_stream(
-    Path(stem_job_id): Path<String>,
+    Path(stem_job_id): Path<Uuid>,
     State(state): State<AppState>,
 ) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let rx = state.stem_job_status.lock().unwrap().get(&stem_job_id).map(|tx| tx.subscribe());
+
     let stream = async_stream::stream! {
-        let mut last_status_json = String::new();
+        let Some(mut rx) = rx else {
+            yield Ok(Event::default().data(json!({ "status": "Failed", "data": "Stem job not found" }).to_string()));
+            return;
+        };
 
         loop {
-            let status = {
-                let stem_jobs = state.stem_jobs.lock().unwrap();
-                stem_jobs.get(&stem_job_id).cloned()
-            };
-
-            match status {
-                Some(status) => {
-                    let json = serde_json::to_string(&status).unwrap();
-                    
-                    // Only send if status changed
-                    if json != last_status_json {
-                        yield Ok(Event::default().data(&json));
-                        last_status_json = json;
-                    }
+            let status = rx.borrow_and_update().clone();
+            yield Ok(Event::default().data(serde_json::to_string(&status).unwrap()));
 
-                    match status {
-                        StemJobStatus::Completed(_) | StemJobStatus::Failed(_) => {
-                            break;
-                        }
-                        _ => {}
+            if matches!(status, StemJobStatus::Completed(_) | StemJobStatus::Failed(_) | StemJobStatus::InvalidJob { .. }) {
+                break;
+            }
+
+            if rx.changed().await.is_err() {
+                break;
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Bridge a stem job's broadcast progress channel into SSE, keyed off a mix
+/// version rather than a raw job id. A reconnecting client re-attaches to
+/// the same broadcast channel and keeps receiving events; only the initial
+/// progress/stage state can be missed if the job finished before the
+/// reconnect (use `/api/stem-jobs/:id/status` to read that back from
+/// `job_queue` instead).
+async fn version_stem_progress_stream(
+    Path(version_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let stem_job_id = sqlx::query!(
+        "SELECT stem_job_id FROM mix_versions WHERE id = $1",
+        version_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .ok()
+    .flatten()
+    .and_then(|row| row.stem_job_id);
+
+    let stream = async_stream::stream! {
+        let Some(stem_job_id) = stem_job_id else {
+            yield Ok(Event::default().data(json!({ "error": "Version has no stem job" }).to_string()));
+            return;
+        };
+
+        let mut rx = get_or_create_stem_channel(&state, &stem_job_id).subscribe();
+
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let is_done = matches!(event, StemProgressEvent::Done { .. });
+                    let json = serde_json::to_string(&event).unwrap_or_default();
+                    yield Ok(Event::default().data(json));
+                    if is_done {
+                        break;
                     }
                 }
-                None => {
-                    yield Ok(Event::default().data(json!({ "status": "Failed", "data": "Stem job not found" }).to_string()));
-                    break;
-                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
             }
-
-            tokio::time::sleep(Duration::from_millis(300)).await; // Faster polling for progress
         }
     };
 
     Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
-fn update_stem_job_status(state: &AppState, job_id: &str, status: StemJobStatus) {
-    let mut stem_jobs = state.stem_jobs.lock().unwrap();
-    stem_jobs.insert(job_id.to_string(), status);
+/// Persist the job's live status into its `job_queue` row (always carrying
+/// `mix_path`/`ref_path` along, so a crash-reclaim via the reaper still has
+/// what it needs to rerun the job), push it into the job's watch channel for
+/// `/api/stems/:id`, and push the same progress over the broadcast channel
+/// for any client subscribed to `/api/versions/:id/stem-progress`.
+async fn report_stem_progress(state: &AppState, job_queue_id: Uuid, job: &StemSeparationJob) {
+    let value = serde_json::to_value(job).unwrap_or_default();
+    if let Err(e) = job_queue::update_progress(&state.db, job_queue_id, &value).await {
+        eprintln!("[job_queue] failed to persist stem progress: {}", e);
+    }
+
+    send_stem_job_status(state, job_queue_id, &job.status);
+
+    if let StemJobStatus::Separating { progress, ref stage, attempts } = job.status {
+        let _ = get_or_create_stem_channel(state, &job_queue_id.to_string())
+            .send(StemProgressEvent::Progress { progress, stage: stage.clone(), attempts });
+    }
+}
+
+/// Broadcast the terminal status of a stem job to any subscribed SSE client,
+/// over both the watch channel (`/api/stems/:id`) and the broadcast channel
+/// (`/api/versions/:id/stem-progress`).
+fn report_stem_done(state: &AppState, stem_job_id: &str, result: StemJobStatus) {
+    if let Ok(id) = Uuid::parse_str(stem_job_id) {
+        send_stem_job_status(state, id, &result);
+    }
+    let _ = get_or_create_stem_channel(state, stem_job_id).send(StemProgressEvent::Done { result });
+}
+
+/// Insert a stem-separation job onto the durable queue, returning its id
+/// (used as the `stem_job_id` everywhere else). The background worker
+/// spawned in `main` claims and runs it.
+async fn enqueue_stem_separation(
+    state: &AppState,
+    mix_path: &std::path::Path,
+    ref_path: &std::path::Path,
+) -> Result<String, String> {
+    let job = serde_json::to_value(StemSeparationJob {
+        mix_path: mix_path.to_path_buf(),
+        ref_path: ref_path.to_path_buf(),
+        status: StemJobStatus::Queued,
+    })
+    .map_err(|e| format!("Failed to serialize stem job: {}", e))?;
+
+    let id = job_queue::enqueue(&state.db, STEM_SEPARATION_QUEUE, &job).await?;
+
+    // Pre-register both channels so a client that subscribes before the
+    // worker claims the job doesn't miss the first progress events.
+    get_or_create_stem_channel(state, &id.to_string());
+    state
+        .stem_job_status
+        .lock()
+        .unwrap()
+        .insert(id, tokio::sync::watch::channel(StemJobStatus::Queued).0);
+
+    Ok(id.to_string())
+}
+
+/// Background loop that claims queued stem-separation jobs one at a time and
+/// runs them to completion, retrying on failure via `job_queue::fail_or_retry`.
+async fn stem_separation_worker(state: AppState) {
+    loop {
+        match job_queue::claim_job(&state.db, STEM_SEPARATION_QUEUE).await {
+            Ok(Some(row)) => {
+                let raw_job = row.job.clone();
+                let job: StemSeparationJob = match serde_json::from_value(row.job) {
+                    Ok(job) => job,
+                    Err(e) => {
+                        eprintln!("[job_queue] dead-lettering malformed stem job {}: {}", row.id, e);
+                        let _ = job_queue::dead_letter(&state.db, row.id, &raw_job, &e.to_string()).await;
+                        send_stem_job_status(&state, row.id, &StemJobStatus::InvalidJob { error: e.to_string(), raw: raw_job });
+                        continue;
+                    }
+                };
+
+                let result = process_stem_separation(
+                    state.clone(),
+                    row.id,
+                    job.mix_path.clone(),
+                    job.ref_path.clone(),
+                    row.retries + 1,
+                )
+                .await;
+                let stem_job_id = row.id.to_string();
+
+                match result {
+                    Ok(stems) => {
+                        let final_status = StemJobStatus::Completed(stems);
+                        let final_job = serde_json::to_value(StemSeparationJob {
+                            status: final_status.clone(),
+                            ..job
+                        })
+                        .unwrap_or_default();
+                        if let Err(e) = job_queue::complete_job(&state.db, row.id, &final_job).await {
+                            eprintln!("[job_queue] failed to mark job {} completed: {}", row.id, e);
+                        }
+                        report_stem_done(&state, &stem_job_id, final_status);
+                    }
+                    Err(e) => {
+                        // Only broadcast a terminal Failed event once the job is
+                        // actually out of retries; mid-retry, the next attempt's
+                        // progress events supersede this one.
+                        match job_queue::fail_or_retry(&state.db, row.id, &e).await {
+                            Ok(true) => report_stem_done(&state, &stem_job_id, StemJobStatus::Failed(e)),
+                            Ok(false) => {}
+                            Err(db_err) => eprintln!("[job_queue] failed to requeue job {}: {}", row.id, db_err),
+                        }
+                    }
+                }
+            }
+            Ok(None) => {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Err(e) => {
+                eprintln!("[job_queue] failed to claim stem job: {}", e);
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+/// Background loop that resets `running` jobs whose heartbeat has gone
+/// stale (crashed worker) back to `new` so they get picked up again.
+async fn job_queue_reaper(state: AppState) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        match job_queue::reap_stale(&state.db).await {
+            Ok(0) => {}
+            Ok(n) => eprintln!("[job_queue] reaped {} stale job(s)", n),
+            Err(e) => eprintln!("[job_queue] reaper error: {}", e),
+        }
+    }
 }
 
 // --- Stem Separation Processing ---
 
 async fn process_stem_separation(
     state: AppState,
-    stem_job_id: String,
+    job_queue_id: Uuid,
     mix_path: PathBuf,
     ref_path: PathBuf,
-) {
-    use std::collections::HashMap;
-    
+    attempts: i32,
+) -> Result<StemAnalysisResult, String> {
+    let stem_job_id = job_queue_id.to_string();
+    let progress_job = |status: StemJobStatus| StemSeparationJob {
+        mix_path: mix_path.clone(),
+        ref_path: ref_path.clone(),
+        status,
+    };
+
     // Update: Starting separation
-    update_stem_job_status(
+    report_stem_progress(
         &state,
-        &stem_job_id,
-        StemJobStatus::Separating {
-            progress: 0,
-            stage: "Initializing Demucs...".to_string(),
-        },
-    );
+        job_queue_id,
+        &progress_job(StemJobStatus::Separating { progress: 0, stage: "Initializing Demucs...".to_string(), attempts }),
+    )
+    .await;
 
     // Create output directories
     let mix_stems_dir = PathBuf::from(&state.upload_dir)
@@ -587,57 +1146,48 @@ async fn process_stem_separation(
         .join(&stem_job_id)
         .join("reference");
 
-    if let Err(e) = fs::create_dir_all(&mix_stems_dir).await {
-        update_stem_job_status(
-            &state,
-            &stem_job_id,
-            StemJobStatus::Failed(format!("Failed to create output dir: {}", e)),
-        );
-        return;
-    }
-    if let Err(e) = fs::create_dir_all(&ref_stems_dir).await {
-        update_stem_job_status(
-            &state,
-            &stem_job_id,
-            StemJobStatus::Failed(format!("Failed to create ref output dir: {}", e)),
-        );
-        return;
-    }
+    fs::create_dir_all(&mix_stems_dir)
+        .await
+        .map_err(|e| format!("Failed to create output dir: {}", e))?;
+    fs::create_dir_all(&ref_stems_dir)
+        .await
+        .map_err(|e| format!("Failed to create ref output dir: {}", e))?;
 
     // Separate mix stems with real-time progress
-    update_stem_job_status(
+    report_stem_progress(
         &state,
-        &stem_job_id,
-        StemJobStatus::Separating {
-            progress: 2,
-            stage: "Starting mix stem separation...".to_string(),
-        },
-    );
+        job_queue_id,
+        &progress_job(StemJobStatus::Separating { progress: 2, stage: "Starting mix stem separation...".to_string(), attempts }),
+    )
+    .await;
 
     // Use channel-based progress for mix separation
     let mix_result = tokio::task::spawn_blocking({
         let mix = mix_path.clone();
+        let ref_for_job = ref_path.clone();
         let out_dir = mix_stems_dir.clone();
         let state_clone = state.clone();
-        let job_id = stem_job_id.clone();
-        
+        let rt_handle = tokio::runtime::Handle::current();
+
         move || {
             let (rx, handle) = stem_separation::separate_stems_with_progress(&mix, &out_dir);
-            
+
             // Poll for progress updates
             loop {
                 match rx.recv_timeout(std::time::Duration::from_millis(100)) {
                     Ok(progress) => {
                         // Scale mix progress from 0-100 to 5-45
                         let scaled = 5 + (progress.progress as u32 * 40 / 100) as u8;
-                        update_stem_job_status(
-                            &state_clone,
-                            &job_id,
-                            StemJobStatus::Separating {
+                        let job = StemSeparationJob {
+                            mix_path: mix.clone(),
+                            ref_path: ref_for_job.clone(),
+                            status: StemJobStatus::Separating {
                                 progress: scaled,
                                 stage: format!("Mix: {}", progress.stage),
+                                attempts,
                             },
-                        );
+                        };
+                        rt_handle.block_on(report_stem_progress(&state_clone, job_queue_id, &job));
                     }
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                         // Check if the thread is done
@@ -650,65 +1200,53 @@ async fn process_stem_separation(
                     }
                 }
             }
-            
+
             handle.join().unwrap_or_else(|_| Err("Thread panicked".to_string()))
         }
     })
+    .with_poll_timer("demucs_mix_separation")
     .await;
 
     let mix_stems = match mix_result {
         Ok(Ok(result)) => result.stems.unwrap_or_default(),
-        Ok(Err(e)) => {
-            update_stem_job_status(
-                &state,
-                &stem_job_id,
-                StemJobStatus::Failed(format!("Mix separation failed: {}", e)),
-            );
-            return;
-        }
-        Err(e) => {
-            update_stem_job_status(
-                &state,
-                &stem_job_id,
-                StemJobStatus::Failed(format!("Task panic: {}", e)),
-            );
-            return;
-        }
+        Ok(Err(e)) => return Err(format!("Mix separation failed: {}", e)),
+        Err(e) => return Err(format!("Task panic: {}", e)),
     };
 
     // Separate reference stems with real-time progress
-    update_stem_job_status(
+    report_stem_progress(
         &state,
-        &stem_job_id,
-        StemJobStatus::Separating {
-            progress: 50,
-            stage: "Starting reference stem separation...".to_string(),
-        },
-    );
+        job_queue_id,
+        &progress_job(StemJobStatus::Separating { progress: 50, stage: "Starting reference stem separation...".to_string(), attempts }),
+    )
+    .await;
 
     let ref_result = tokio::task::spawn_blocking({
+        let mix_path = mix_path.clone();
         let ref_p = ref_path.clone();
         let out_dir = ref_stems_dir.clone();
         let state_clone = state.clone();
-        let job_id = stem_job_id.clone();
-        
+        let rt_handle = tokio::runtime::Handle::current();
+
         move || {
             let (rx, handle) = stem_separation::separate_stems_with_progress(&ref_p, &out_dir);
-            
+
             // Poll for progress updates
             loop {
                 match rx.recv_timeout(std::time::Duration::from_millis(100)) {
                     Ok(progress) => {
                         // Scale ref progress from 0-100 to 50-90
                         let scaled = 50 + (progress.progress as u32 * 40 / 100) as u8;
-                        update_stem_job_status(
-                            &state_clone,
-                            &job_id,
-                            StemJobStatus::Separating {
+                        let job = StemSeparationJob {
+                            mix_path: mix_path.clone(),
+                            ref_path: ref_p.clone(),
+                            status: StemJobStatus::Separating {
                                 progress: scaled,
                                 stage: format!("Reference: {}", progress.stage),
+                                attempts,
                             },
-                        );
+                        };
+                        rt_handle.block_on(report_stem_progress(&state_clone, job_queue_id, &job));
                     }
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                         if handle.is_finished() {
@@ -720,131 +1258,56 @@ async fn process_stem_separation(
                     }
                 }
             }
-            
+
             handle.join().unwrap_or_else(|_| Err("Thread panicked".to_string()))
         }
     })
+    .with_poll_timer("demucs_reference_separation")
     .await;
 
     let _ref_stems = match ref_result {
         Ok(Ok(result)) => result.stems.unwrap_or_default(),
-        Ok(Err(e)) => {
-            update_stem_job_status(
-                &state,
-                &stem_job_id,
-                StemJobStatus::Failed(format!("Reference separation failed: {}", e)),
-            );
-            return;
-        }
-        Err(e) => {
-            update_stem_job_status(
-                &state,
-                &stem_job_id,
-                StemJobStatus::Failed(format!("Task panic: {}", e)),
-            );
-            return;
-        }
+        Ok(Err(e)) => return Err(format!("Reference separation failed: {}", e)),
+        Err(e) => return Err(format!("Task panic: {}", e)),
     };
 
     // Analyze each stem
-    update_stem_job_status(
+    report_stem_progress(
         &state,
-        &stem_job_id,
-        StemJobStatus::Analyzing {
-            stem: "all stems".to_string(),
-        },
-    );
-
-    // Build result with stem metrics (simplified for now - just paths)
-    let mut stem_metrics: HashMap<String, StemMetrics> = HashMap::new();
-    for (stem_name, stem_path) in &mix_stems {
-        stem_metrics.insert(
-            stem_name.clone(),
-            StemMetrics {
-                file_path: stem_path.clone(),
-                integrated_lufs: -14.0, // TODO: Run actual analysis
-                spectral_centroid: 2000.0,
-                spectral_rolloff: 8000.0,
-            },
-        );
-    }
-
-    // Complete!
-    update_stem_job_status(
-        &state,
-        &stem_job_id,
-        StemJobStatus::Completed(StemAnalysisResult {
-            stems: stem_metrics,
-        }),
-    );
-}
-
-// --- AI Helper ---
-
-#[derive(Serialize)]
-struct ChatMessagePayload {
-    role: String,
-    content: String,
-}
-
-async fn request_ai_completion(api_key: &str, metrics: &ComparisonResult) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let model_id = "gpt-5"; // Or "gpt-4o"
-
-    // Prepare LLM Prompt
-    let prompt = format!(
-        "Analyze these audio metrics for a mix vs reference.
-        Be extremely concise. Bullet points only. No fluff.
-        
-        METRICS:
-        1. LOUDNESS: {:.1} LUFS (Ref: {:.1})
-        2. DYNAMICS: {:.1} LU (Ref: {:.1})
-        3. WIDTH: {:.1} (Ref: {:.1})
-        4. BPM: {:.1} (Ref: {:.1})
-        
-        Provide 3 short, actionable mastering steps.",
-        
-        metrics.mix.integrated_lufs, metrics.reference.integrated_lufs,
-        metrics.mix.loudness_range, metrics.reference.loudness_range,
-        metrics.mix.dynamic_complexity, metrics.reference.dynamic_complexity, // Using dynamic_complexity as proxy for width/punch in this simplified prompt
-        metrics.mix.bpm, metrics.reference.bpm
-    );
-
-    let messages = vec![ChatMessagePayload {
-        role: "system".to_string(),
-        content: "You are a concise Audio Engineer. Output JSON-like or very short text.".to_string(),
-    }, ChatMessagePayload {
-        role: "user".to_string(),
-        content: prompt,
-    }];
-
-    let request_body = json!({
-        "model": model_id,
-        "messages": messages,
-        "reasoning_effort": "low", 
-        "stream": false
-    });
+        job_queue_id,
+        &progress_job(StemJobStatus::Analyzing { stem: "all stems".to_string() }),
+    )
+    .await;
 
-    let res = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request_body)
-        .send()
+    // Compute real loudness/spectral metrics for every stem concurrently.
+    let stems_for_analysis = mix_stems.clone();
+    let stem_metrics = tokio::task::spawn_blocking(move || stem_metrics::analyze_stems_concurrent(&stems_for_analysis))
+        .with_poll_timer("stem_metrics_analysis")
         .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    if !res.status().is_success() {
-        let error_text = res.text().await.unwrap_or_default();
-        return Err(format!("OpenAI error: {}", error_text));
-    }
+        .map_err(|e| format!("Task panic: {}", e))?;
+
+    // Transcribe the vocal stem, if one was separated out. Best-effort: a
+    // transcription failure is logged and leaves `vocal_transcript` empty
+    // rather than failing an otherwise-complete analysis.
+    let vocal_transcript = match mix_stems.get("vocals") {
+        Some(vocal_path) => {
+            report_stem_progress(
+                &state,
+                job_queue_id,
+                &progress_job(StemJobStatus::Transcribing { stem: "vocals".to_string() }),
+            )
+            .await;
 
-    let body: serde_json::Value = res.json().await.map_err(|e| format!("Parse error: {}", e))?;
-    
-    // Extract content
-    let content = body["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("No content")
-        .to_string();
+            match state.transcription.transcribe(vocal_path).with_poll_timer("vocal_transcription").await {
+                Ok(segments) => Some(segments),
+                Err(e) => {
+                    eprintln!("[transcription] failed to transcribe vocal stem: {}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
 
-    Ok(content)
+    Ok(StemAnalysisResult { stems: stem_metrics, vocal_transcript })
 }
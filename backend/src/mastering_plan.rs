@@ -0,0 +1,192 @@
+//! Structured, schema-validated mastering advice: instead of free-form
+//! prose, the AI backend returns a `MasteringPlan` of fixed-shape steps that
+//! the rest of the app can render without parsing natural language.
+//!
+//! Uses the "instructor" pattern: the prompt includes a JSON schema
+//! describing `MasteringPlan`, the reply is parsed and validated, and on
+//! failure the error is appended as a new user turn and re-requested, up to
+//! `max_retries()` times.
+
+use crate::audio_analysis::ComparisonResult;
+use crate::llm::{ChatMessage, LlmProvider, Usage};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Number of steps a valid plan must contain.
+const REQUIRED_STEPS: usize = 3;
+/// Default retry budget, overridable via `MIXANALYZER_MASTERING_PLAN_MAX_RETRIES`.
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+fn max_retries() -> u32 {
+    std::env::var("MIXANALYZER_MASTERING_PLAN_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_RETRIES)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MasteringTarget {
+    Loudness,
+    Dynamics,
+    Width,
+    Bpm,
+    Eq,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Increase,
+    Decrease,
+    Leave,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasteringStep {
+    pub target: MasteringTarget,
+    pub direction: Direction,
+    pub amount: Option<f32>,
+    pub rationale: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasteringPlan {
+    pub steps: Vec<MasteringStep>,
+}
+
+/// JSON schema sent to the model describing the exact shape `MasteringPlan`
+/// expects back.
+const PLAN_SCHEMA: &str = r#"{
+  "type": "object",
+  "properties": {
+    "steps": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "target": { "type": "string", "enum": ["loudness", "dynamics", "width", "bpm", "eq"] },
+          "direction": { "type": "string", "enum": ["increase", "decrease", "leave"] },
+          "amount": { "type": ["number", "null"] },
+          "rationale": { "type": "string" }
+        },
+        "required": ["target", "direction", "amount", "rationale"]
+      }
+    }
+  },
+  "required": ["steps"]
+}"#;
+
+/// Which `AudioMetrics` field(s) back each `MasteringTarget`, named here
+/// (rather than just in the prompt below) so `validate` can confirm every
+/// target the model names actually corresponds to something in
+/// `ComparisonResult`. `Width` has no literal stereo-width metric, so it's
+/// backed by `dynamic_complexity` as an imperfect proxy; `Eq` is backed by
+/// the spectral metrics shown to the model as line 5 of the prompt. The
+/// match is exhaustive, so adding a `MasteringTarget` variant without
+/// updating this mapping (and the prompt) fails to compile.
+fn target_metric_names(target: MasteringTarget) -> &'static [&'static str] {
+    match target {
+        MasteringTarget::Loudness => &["integrated_lufs"],
+        MasteringTarget::Dynamics => &["loudness_range"],
+        MasteringTarget::Width => &["dynamic_complexity"],
+        MasteringTarget::Bpm => &["bpm"],
+        MasteringTarget::Eq => &["spectral_centroid", "spectral_rolloff"],
+    }
+}
+
+/// Invariants the rest of the app relies on: exactly `REQUIRED_STEPS` steps,
+/// every present `amount` finite, and every step's `target` backed by a real
+/// `ComparisonResult` metric (see `target_metric_names`).
+fn validate(plan: &MasteringPlan) -> Result<(), String> {
+    if plan.steps.len() != REQUIRED_STEPS {
+        return Err(format!("Expected exactly {} steps, got {}", REQUIRED_STEPS, plan.steps.len()));
+    }
+
+    for step in &plan.steps {
+        if target_metric_names(step.target).is_empty() {
+            return Err(format!("Step target {:?} has no backing metric in ComparisonResult", step.target));
+        }
+
+        if let Some(amount) = step.amount {
+            if !amount.is_finite() {
+                return Err(format!("Step for {:?} has a non-finite amount: {}", step.target, amount));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn initial_messages(metrics: &ComparisonResult) -> Vec<ChatMessage> {
+    let prompt = format!(
+        "Analyze these audio metrics for a mix vs reference and respond with ONLY JSON \
+         matching this schema (no prose, no markdown fences):\n{}\n\n\
+         METRICS:\n\
+         1. LOUDNESS: {:.1} LUFS (Ref: {:.1})\n\
+         2. DYNAMICS: {:.1} LU (Ref: {:.1})\n\
+         3. WIDTH: {:.1} (Ref: {:.1})\n\
+         4. BPM: {:.1} (Ref: {:.1})\n\
+         5. EQ: spectral centroid {:.0} Hz (Ref: {:.0}), rolloff {:.0} Hz (Ref: {:.0})\n\n\
+         Respond with exactly {} steps.",
+        PLAN_SCHEMA,
+        metrics.mix.integrated_lufs, metrics.reference.integrated_lufs,
+        metrics.mix.loudness_range, metrics.reference.loudness_range,
+        metrics.mix.dynamic_complexity, metrics.reference.dynamic_complexity,
+        metrics.mix.bpm, metrics.reference.bpm,
+        metrics.mix.spectral_centroid, metrics.reference.spectral_centroid,
+        metrics.mix.spectral_rolloff, metrics.reference.spectral_rolloff,
+        REQUIRED_STEPS
+    );
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "You are a concise Audio Engineer. Respond with ONLY valid JSON, no commentary.".to_string(),
+        },
+        ChatMessage { role: "user".to_string(), content: prompt },
+    ]
+}
+
+/// Request a `MasteringPlan` from `llm`, streaming each attempt's raw tokens
+/// to `tx` (same watch-channel forwarding as free-form advice used to) so a
+/// client still sees live output while the plan is generated. On a parse or
+/// validation failure, appends the error as a new user turn to the same
+/// message list and re-requests, up to `max_retries()` times. Returns the
+/// `Usage` summed across every attempt, since each one is a separately
+/// billed completion.
+pub async fn request_mastering_plan(
+    llm: &dyn LlmProvider,
+    metrics: &ComparisonResult,
+    tx: mpsc::UnboundedSender<String>,
+) -> Result<(MasteringPlan, Usage), String> {
+    let mut messages = initial_messages(metrics);
+    let mut last_err = String::new();
+    let mut usage = Usage::default();
+
+    for attempt in 0..=max_retries() {
+        let (reply, attempt_usage) = llm.complete_stream(&messages, tx.clone()).await?;
+        usage.add(attempt_usage);
+
+        match serde_json::from_str::<MasteringPlan>(reply.trim())
+            .map_err(|e| format!("Failed to parse plan JSON: {}", e))
+            .and_then(|plan| validate(&plan).map(|_| plan))
+        {
+            Ok(plan) => return Ok((plan, usage)),
+            Err(e) => {
+                eprintln!("[mastering_plan] attempt {}/{} invalid: {}", attempt + 1, max_retries() + 1, e);
+                last_err = e.clone();
+                messages.push(ChatMessage { role: "assistant".to_string(), content: reply });
+                messages.push(ChatMessage {
+                    role: "user".to_string(),
+                    content: format!(
+                        "That response was invalid: {}. Reply again with ONLY corrected JSON matching the schema.",
+                        e
+                    ),
+                });
+            }
+        }
+    }
+
+    Err(last_err)
+}
@@ -0,0 +1,84 @@
+//! A future combinator that times how long a named operation spends being
+//! polled, logging a warning when a single poll or the operation's total
+//! wall-clock time blows past a threshold. Wrapping a long-running await
+//! point (an external process, an HTTP call) with this turns "the job looks
+//! stuck" into a log line naming exactly which stage is slow.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project::pin_project;
+
+/// A single `poll` call taking longer than this is logged as a stall.
+pub const DEFAULT_POLL_WARN_THRESHOLD: Duration = Duration::from_secs(5);
+/// An operation still running after this much total wall-clock time is
+/// logged as slow (once, not on every subsequent poll).
+pub const DEFAULT_TOTAL_WARN_THRESHOLD: Duration = Duration::from_secs(60);
+
+#[pin_project]
+pub struct PollTimer<F> {
+    #[pin]
+    inner: F,
+    name: &'static str,
+    poll_warn_threshold: Duration,
+    total_warn_threshold: Duration,
+    started_at: Option<Instant>,
+    total_warned: bool,
+}
+
+impl<F: Future> Future for PollTimer<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let started_at = *this.started_at.get_or_insert_with(Instant::now);
+
+        let poll_start = Instant::now();
+        let result = this.inner.poll(cx);
+        let poll_elapsed = poll_start.elapsed();
+
+        if poll_elapsed > *this.poll_warn_threshold {
+            eprintln!(
+                "[poll_timer] {} stalled for {:.1}s in a single poll",
+                this.name,
+                poll_elapsed.as_secs_f64()
+            );
+        }
+
+        let total_elapsed = started_at.elapsed();
+        if !*this.total_warned && total_elapsed > *this.total_warn_threshold {
+            *this.total_warned = true;
+            eprintln!(
+                "[poll_timer] {} still running after {:.1}s (warn threshold {:.0}s)",
+                this.name,
+                total_elapsed.as_secs_f64(),
+                this.total_warn_threshold.as_secs_f64()
+            );
+        }
+
+        if result.is_ready() {
+            eprintln!("[poll_timer] {} finished in {:.1}s", this.name, total_elapsed.as_secs_f64());
+        }
+
+        result
+    }
+}
+
+/// Extension trait that adds `.with_poll_timer(name)` to any future.
+pub trait WithPollTimer: Future + Sized {
+    /// Time this future under `name`, using the default warn thresholds.
+    fn with_poll_timer(self, name: &'static str) -> PollTimer<Self> {
+        PollTimer {
+            inner: self,
+            name,
+            poll_warn_threshold: DEFAULT_POLL_WARN_THRESHOLD,
+            total_warn_threshold: DEFAULT_TOTAL_WARN_THRESHOLD,
+            started_at: None,
+            total_warned: false,
+        }
+    }
+}
+
+impl<F: Future> WithPollTimer for F {}
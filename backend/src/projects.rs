@@ -4,7 +4,8 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::AppState;
+use crate::job_queue;
+use crate::{ApiResponse, AppState};
 
 #[derive(Serialize, Deserialize)]
 pub struct Project {
@@ -20,32 +21,41 @@ pub struct CreateProjectRequest {
 
 pub async fn list_projects(
     State(state): State<AppState>,
-) -> Json<Vec<Project>> {
-    let projects = sqlx::query_as!(
+) -> Json<ApiResponse<Vec<Project>>> {
+    match sqlx::query_as!(
         Project,
         "SELECT id, name, created_at FROM projects ORDER BY created_at DESC"
     )
     .fetch_all(&state.db)
     .await
-    .unwrap_or_default();
-
-    Json(projects)
+    {
+        Ok(projects) => ApiResponse::success(projects),
+        Err(e) => ApiResponse::fatal(format!("Database error: {}", e)),
+    }
 }
 
 pub async fn create_project(
     State(state): State<AppState>,
     Json(payload): Json<CreateProjectRequest>,
-) -> Json<Project> {
-    let project = sqlx::query_as!(
+) -> Json<ApiResponse<Project>> {
+    if payload.name.trim().is_empty() {
+        return ApiResponse::failure("Project name cannot be empty");
+    }
+
+    match sqlx::query_as!(
         Project,
         "INSERT INTO projects (name) VALUES ($1) RETURNING id, name, created_at",
         payload.name
     )
     .fetch_one(&state.db)
     .await
-    .expect("Failed to create project");
-
-    Json(project)
+    {
+        Ok(project) => ApiResponse::success(project),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            ApiResponse::failure(format!("A project named \"{}\" already exists", payload.name))
+        }
+        Err(e) => ApiResponse::fatal(format!("Database error: {}", e)),
+    }
 }
 
 #[derive(Serialize)]
@@ -73,35 +83,45 @@ pub struct ReferenceTrack {
 pub async fn get_project(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Json<ProjectDetails> {
-    let project = sqlx::query_as!(
+) -> Json<ApiResponse<ProjectDetails>> {
+    let project = match sqlx::query_as!(
         Project,
         "SELECT id, name, created_at FROM projects WHERE id = $1",
         id
     )
-    .fetch_one(&state.db)
+    .fetch_optional(&state.db)
     .await
-    .expect("Project not found");
+    {
+        Ok(Some(project)) => project,
+        Ok(None) => return ApiResponse::failure("Project not found"),
+        Err(e) => return ApiResponse::fatal(format!("Database error: {}", e)),
+    };
 
-    let versions = sqlx::query_as!(
+    let versions = match sqlx::query_as!(
         MixVersion,
         "SELECT id, version_name, created_at, stem_job_id FROM mix_versions WHERE project_id = $1 ORDER BY created_at DESC",
         id
     )
     .fetch_all(&state.db)
     .await
-    .unwrap_or_default();
+    {
+        Ok(versions) => versions,
+        Err(e) => return ApiResponse::fatal(format!("Database error: {}", e)),
+    };
 
-    let references = sqlx::query_as!(
+    let references = match sqlx::query_as!(
         ReferenceTrack,
         "SELECT id, name, created_at FROM reference_tracks WHERE project_id = $1 ORDER BY created_at DESC",
         id
     )
     .fetch_all(&state.db)
     .await
-    .unwrap_or_default();
+    {
+        Ok(references) => references,
+        Err(e) => return ApiResponse::fatal(format!("Database error: {}", e)),
+    };
 
-    Json(ProjectDetails {
+    ApiResponse::success(ProjectDetails {
         project,
         versions,
         references,
@@ -119,97 +139,97 @@ pub struct AnalysisRecord {
 pub async fn get_analysis_by_version(
     State(state): State<AppState>,
     Path(version_id): Path<Uuid>,
-) -> Json<Option<AnalysisRecord>> {
-    let analysis = sqlx::query_as!(
+) -> Json<ApiResponse<Option<AnalysisRecord>>> {
+    match sqlx::query_as!(
         AnalysisRecord,
         "SELECT id, metrics, ai_report, created_at FROM analyses WHERE mix_version_id = $1 ORDER BY created_at DESC LIMIT 1",
         version_id
     )
     .fetch_optional(&state.db)
     .await
-    .unwrap_or_default();
-
-    Json(analysis)
+    {
+        Ok(analysis) => ApiResponse::success(analysis),
+        Err(e) => ApiResponse::fatal(format!("Database error: {}", e)),
+    }
 }
 
-/// Delete a mix version (cascades to analyses)
 /// Delete a mix version (cascades to analyses) and clean up files
 pub async fn delete_version(
     State(state): State<AppState>,
     Path(version_id): Path<Uuid>,
-) -> Json<serde_json::Value> {
+) -> Json<ApiResponse<()>> {
     // 1. Fetch file paths and IDs before deletion
-    let version_info = sqlx::query!(
+    let version_info = match sqlx::query!(
         "SELECT file_path, stem_job_id FROM mix_versions WHERE id = $1",
         version_id
     )
     .fetch_optional(&state.db)
     .await
+    {
+        Ok(Some(info)) => info,
+        Ok(None) => return ApiResponse::failure("Version not found"),
+        Err(e) => return ApiResponse::fatal(format!("Database error: {}", e)),
+    };
+
+    // Get associated reference track
+    let ref_info = sqlx::query!(
+        "SELECT rt.id, rt.file_path
+         FROM reference_tracks rt
+         JOIN analyses a ON a.reference_track_id = rt.id
+         WHERE a.mix_version_id = $1",
+        version_id
+    )
+    .fetch_optional(&state.db)
+    .await
     .unwrap_or(None);
 
-    if let Some(info) = version_info {
-        // Get associated reference track
-        let ref_info = sqlx::query!(
-            "SELECT rt.id, rt.file_path 
-             FROM reference_tracks rt 
-             JOIN analyses a ON a.reference_track_id = rt.id 
-             WHERE a.mix_version_id = $1",
-            version_id
-        )
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
+    // 2. Delete physical files
 
-        // 2. Delete physical files
-        
-        // Delete Mix File
-        let mix_path = std::path::Path::new(&info.file_path);
-        if mix_path.exists() {
-            let _ = tokio::fs::remove_file(mix_path).await;
-        }
+    // Delete Mix File
+    let mix_path = std::path::Path::new(&version_info.file_path);
+    if mix_path.exists() {
+        let _ = tokio::fs::remove_file(mix_path).await;
+    }
 
-        // Delete Reference File
-        if let Some(ref ref_data) = ref_info {
-            let ref_path = std::path::Path::new(&ref_data.file_path);
-            if ref_path.exists() {
-                let _ = tokio::fs::remove_file(ref_path).await;
-            }
+    // Delete Reference File
+    if let Some(ref ref_data) = ref_info {
+        let ref_path = std::path::Path::new(&ref_data.file_path);
+        if ref_path.exists() {
+            let _ = tokio::fs::remove_file(ref_path).await;
         }
+    }
 
-        // Delete Stems Directory
-        if let Some(stem_id) = info.stem_job_id {
-            let stems_dir = std::path::Path::new(&state.upload_dir).join("stems").join(stem_id);
-            if stems_dir.exists() {
-                let _ = tokio::fs::remove_dir_all(stems_dir).await;
-            }
+    // Delete Stems Directory
+    if let Some(stem_id) = version_info.stem_job_id {
+        let stems_dir = std::path::Path::new(&state.upload_dir).join("stems").join(stem_id);
+        if stems_dir.exists() {
+            let _ = tokio::fs::remove_dir_all(stems_dir).await;
         }
+    }
+
+    // 3. Delete DB Records
+
+    // Delete version (cascades to analyses)
+    let result = sqlx::query!(
+        "DELETE FROM mix_versions WHERE id = $1",
+        version_id
+    )
+    .execute(&state.db)
+    .await;
 
-        // 3. Delete DB Records
-        
-        // Delete version (cascades to analyses)
-        let result = sqlx::query!(
-            "DELETE FROM mix_versions WHERE id = $1",
-            version_id
+    // Delete reference track (now orphaned from this analysis)
+    if let Some(ref_data) = ref_info {
+        let _ = sqlx::query!(
+            "DELETE FROM reference_tracks WHERE id = $1",
+            ref_data.id
         )
         .execute(&state.db)
         .await;
+    }
 
-        // Delete reference track (now orphaned from this analysis)
-        if let Some(ref_data) = ref_info {
-            let _ = sqlx::query!(
-                "DELETE FROM reference_tracks WHERE id = $1",
-                ref_data.id
-            )
-            .execute(&state.db)
-            .await;
-        }
-
-        match result {
-            Ok(_) => Json(serde_json::json!({ "success": true })),
-            Err(e) => Json(serde_json::json!({ "success": false, "error": e.to_string() })),
-        }
-    } else {
-        Json(serde_json::json!({ "success": false, "error": "Version not found" }))
+    match result {
+        Ok(_) => ApiResponse::success(()),
+        Err(e) => ApiResponse::fatal(format!("Database error: {}", e)),
     }
 }
 
@@ -223,35 +243,54 @@ pub struct VersionFiles {
 pub async fn get_version_files(
     State(state): State<AppState>,
     Path(version_id): Path<Uuid>,
-) -> Json<Option<VersionFiles>> {
+) -> Json<ApiResponse<VersionFiles>> {
     // Get mix file path from version
-    let version = sqlx::query!(
+    let version = match sqlx::query!(
         "SELECT file_path, project_id FROM mix_versions WHERE id = $1",
         version_id
     )
     .fetch_optional(&state.db)
     .await
-    .ok()
-    .flatten();
+    {
+        Ok(Some(v)) => v,
+        Ok(None) => return ApiResponse::failure("Version not found"),
+        Err(e) => return ApiResponse::fatal(format!("Database error: {}", e)),
+    };
 
-    if let Some(v) = version {
-        // Get reference file path (most recent one for the project)
-        let reference = sqlx::query!(
-            "SELECT file_path FROM reference_tracks WHERE project_id = $1 ORDER BY created_at DESC LIMIT 1",
-            v.project_id
-        )
-        .fetch_optional(&state.db)
-        .await
-        .ok()
-        .flatten();
+    // Get reference file path (most recent one for the project)
+    let reference = match sqlx::query!(
+        "SELECT file_path FROM reference_tracks WHERE project_id = $1 ORDER BY created_at DESC LIMIT 1",
+        version.project_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => return ApiResponse::failure("Version has no reference track"),
+        Err(e) => return ApiResponse::fatal(format!("Database error: {}", e)),
+    };
 
-        if let Some(r) = reference {
-            return Json(Some(VersionFiles {
-                mix_path: v.file_path,
-                ref_path: r.file_path,
-            }));
-        }
-    }
+    ApiResponse::success(VersionFiles {
+        mix_path: version.file_path,
+        ref_path: reference.file_path,
+    })
+}
 
-    Json(None)
+/// Report the live status of a stem-separation job straight from the
+/// `job_queue` table, so a client reconnecting after a server restart (or
+/// after missing SSE events) can still see current progress.
+pub async fn get_stem_job_queue_status(
+    State(state): State<AppState>,
+    Path(job_queue_id): Path<Uuid>,
+) -> Json<ApiResponse<serde_json::Value>> {
+    match job_queue::find(&state.db, job_queue_id).await {
+        Ok(Some(row)) => ApiResponse::success(serde_json::json!({
+            "status": row.status,
+            "retries": row.retries,
+            "heartbeat": row.heartbeat,
+            "job": row.job,
+        })),
+        Ok(None) => ApiResponse::failure("Job not found"),
+        Err(e) => ApiResponse::fatal(e),
+    }
 }
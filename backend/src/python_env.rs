@@ -0,0 +1,144 @@
+//! Resolves the Python interpreter used to run the project's analysis
+//! scripts (`analyze_audio.py`, `separate_stems.py`, `transcribe_vocals.py`)
+//! instead of assuming a hardcoded `../.venv/bin/python` relative to the
+//! current working directory, which only worked when `cargo run` happened
+//! to be invoked from `backend/`.
+//!
+//! Mirrors the lookup strategy rust-analyzer's `ra_toolchain` and uv's
+//! `which` module use: an explicit override first, then the active venv,
+//! then a `$PATH` walk, then well-known relative fallbacks.
+
+use std::path::{Path, PathBuf};
+
+/// Candidate interpreter names to look for on `$PATH`, in preference order.
+#[cfg(not(windows))]
+const PATH_CANDIDATES: &[&str] = &["python3", "python"];
+#[cfg(windows)]
+const PATH_CANDIDATES: &[&str] = &["python3.exe", "python.exe"];
+
+/// Well-known relative locations, tried last, for a project venv that isn't
+/// otherwise surfaced via `$VIRTUAL_ENV`.
+#[cfg(not(windows))]
+const RELATIVE_CANDIDATES: &[&str] = &[".venv/bin/python", "../.venv/bin/python"];
+#[cfg(windows)]
+const RELATIVE_CANDIDATES: &[&str] = &[".venv\\Scripts\\python.exe", "..\\.venv\\Scripts\\python.exe"];
+
+/// Find a usable Python interpreter, trying in order:
+/// 1. `$MIXANALYZER_PYTHON`, an explicit override for unusual setups.
+/// 2. `$VIRTUAL_ENV/bin/python`, the currently-activated venv, if any.
+/// 3. Each directory on `$PATH`, checking for `python3`/`python` (or
+///    `python3.exe`/`python.exe` on Windows) that's actually executable.
+/// 4. Well-known relative venv locations (`.venv/bin/python`,
+///    `../.venv/bin/python`), for the common case of running from the
+///    project root or `backend/` without an activated venv.
+///
+/// Returns every path that was tried if none of them pan out, so a
+/// misconfigured environment is diagnosable from the error alone.
+pub fn resolve_python() -> Result<PathBuf, String> {
+    let mut tried = Vec::new();
+
+    if let Ok(override_path) = std::env::var("MIXANALYZER_PYTHON") {
+        if is_executable_file(Path::new(&override_path)) {
+            return Ok(PathBuf::from(override_path));
+        }
+        tried.push(override_path);
+    }
+
+    if let Ok(venv) = std::env::var("VIRTUAL_ENV") {
+        let candidate = venv_python(Path::new(&venv));
+        if is_executable_file(&candidate) {
+            return Ok(candidate);
+        }
+        tried.push(candidate.display().to_string());
+    }
+
+    if let Ok(path_var) = std::env::var("PATH") {
+        for dir in std::env::split_paths(&path_var) {
+            for name in PATH_CANDIDATES {
+                let candidate = dir.join(name);
+                if is_executable_file(&candidate) {
+                    return Ok(candidate);
+                }
+            }
+        }
+        tried.push(format!("$PATH ({})", PATH_CANDIDATES.join(", ")));
+    }
+
+    for relative in RELATIVE_CANDIDATES {
+        let candidate = PathBuf::from(relative);
+        if is_executable_file(&candidate) {
+            return Ok(candidate);
+        }
+        tried.push(relative.to_string());
+    }
+
+    Err(format!(
+        "Could not find a Python interpreter. Tried: {}. Set $MIXANALYZER_PYTHON to override.",
+        tried.join(", ")
+    ))
+}
+
+#[cfg(not(windows))]
+fn venv_python(venv: &Path) -> PathBuf {
+    venv.join("bin").join("python")
+}
+
+#[cfg(windows)]
+fn venv_python(venv: &Path) -> PathBuf {
+    venv.join("Scripts").join("python.exe")
+}
+
+/// On Unix, verifies the execute bit via the file's permission mode rather
+/// than just checking it exists, since a present-but-non-executable file
+/// should fall through to the next candidate. On Windows there's no execute
+/// bit to check, so existence (already implied by the `.exe` suffix
+/// convention) is enough.
+#[cfg(unix)]
+fn is_executable_file(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn is_executable_file(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Resolve a script bundled with the project (e.g. `analyze_audio.py`)
+/// relative to the project root rather than the current working directory,
+/// so callers work regardless of whether the backend binary is started from
+/// `backend/`, the project root, or somewhere else entirely.
+///
+/// The project root is located by walking up from the interpreter's venv
+/// (`<root>/.venv/...`) when one is in play, or from this crate's own
+/// manifest directory otherwise, so it does not depend on the process's
+/// current working directory.
+pub fn resolve_script(python: &Path, script_name: &str) -> PathBuf {
+    if let Some(root) = project_root_from_interpreter(python) {
+        return root.join(script_name);
+    }
+
+    // Fall back to the directory above `backend/` (this crate), which is
+    // where the analysis scripts live in the project layout.
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .map(|root| root.join(script_name))
+        .unwrap_or_else(|| PathBuf::from(script_name))
+}
+
+/// Walks up from a `.../.venv/bin/python` (or `.../.venv/Scripts/python.exe`
+/// on Windows) interpreter path to the directory containing `.venv`, which
+/// is the project root by convention. Returns `None` for interpreters that
+/// aren't inside a `.venv` (e.g. a system Python found on `$PATH`).
+fn project_root_from_interpreter(python: &Path) -> Option<PathBuf> {
+    python.ancestors().find_map(|dir| {
+        if dir.file_name().map(|n| n == ".venv").unwrap_or(false) {
+            dir.parent().map(PathBuf::from)
+        } else {
+            None
+        }
+    })
+}
@@ -1,9 +1,12 @@
+use crate::python_env;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Result of stem separation
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -21,127 +24,175 @@ pub struct StemProgress {
     pub stage: String,  // Description of current stage
 }
 
-/// Separate audio stems using Demucs with progress channel.
-/// Returns a receiver for progress updates and the final result.
+/// A single Demucs invocation can fail two different ways: the process
+/// itself exits non-zero, or it exits 0 but emits something that isn't the
+/// JSON we expect. `run_separation` keeps these distinct, but the public
+/// entry points convert both to the same plain `String` message, so their
+/// callers (and the job queue) do not see which class a failure was.
+#[derive(Debug, Clone)]
+pub enum SeparationError {
+    ProcessFailed(String),
+    InvalidOutput(String),
+}
+
+impl std::fmt::Display for SeparationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeparationError::ProcessFailed(e) => write!(f, "{}", e),
+            SeparationError::InvalidOutput(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Default stall-detection threshold; overridable via env for slower machines.
+const DEFAULT_STALL_WARN_SECS: u64 = 60;
+
+fn stall_threshold() -> Duration {
+    std::env::var("MIXANALYZER_STALL_THRESHOLD_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(DEFAULT_STALL_WARN_SECS))
+}
+
+/// Separate audio stems using Demucs with progress channel. Returns a
+/// receiver for progress updates and the final result of a single
+/// invocation. Retrying a failed attempt is the caller's job: the
+/// `stem_separation_worker` job-queue loop already retries with backoff
+/// (`job_queue::fail_or_retry`) and that retry is durable across restarts,
+/// so a second retry layer here would just compound into up to
+/// `job_queue::max_retries()` times as many Demucs spawns as intended.
 pub fn separate_stems_with_progress<P: AsRef<Path>>(
     audio_path: P,
     output_dir: P,
 ) -> (mpsc::Receiver<StemProgress>, std::thread::JoinHandle<Result<StemSeparationResult, String>>) {
     let audio_str = audio_path.as_ref().to_string_lossy().to_string();
     let output_str = output_dir.as_ref().to_string_lossy().to_string();
-    
-    let (tx, rx) = mpsc::channel();
-    
-    let handle = std::thread::spawn(move || {
-        // Spawn Python process with piped stderr for progress
-        let mut child = Command::new("../.venv/bin/python")
-            .arg("separate_stems.py")
-            .arg(&audio_str)
-            .arg(&output_str)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to execute python script: {}", e))?;
-
-        // Read stderr for progress updates in a separate thread
-        if let Some(stderr) = child.stderr.take() {
-            let tx_clone = tx.clone();
-            std::thread::spawn(move || {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        // Parse progress lines: "PROGRESS:50:Separating stems"
-                        if line.starts_with("PROGRESS:") {
-                            let parts: Vec<&str> = line.splitn(3, ':').collect();
-                            if parts.len() == 3 {
-                                if let Ok(progress) = parts[1].parse::<u8>() {
-                                    let stage = parts[2].to_string();
-                                    let _ = tx_clone.send(StemProgress { progress, stage });
-                                }
-                            }
-                        }
-                        // Also log other stderr lines for debugging
-                        eprintln!("[Demucs] {}", line);
-                    }
-                }
-            });
-        }
 
-        // Wait for process to finish and get stdout
-        let output = child
-            .wait_with_output()
-            .map_err(|e| format!("Failed to wait for python script: {}", e))?;
-
-        if !output.status.success() {
-            return Err(format!(
-                "stem separation failed with exit code: {:?}",
-                output.status.code()
-            ));
-        }
-
-        // Parse JSON output from stdout
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let result: StemSeparationResult = serde_json::from_str(&stdout)
-            .map_err(|e| format!("Failed to parse JSON output: {} (Output: {})", e, stdout))?;
-
-        if !result.success {
-            return Err(result.error.unwrap_or_else(|| "Unknown error".to_string()));
-        }
+    let (tx, rx) = mpsc::channel();
 
-        Ok(result)
+    let handle = std::thread::spawn(move || {
+        run_separation(&audio_str, &output_str, Some(&tx)).map_err(|e| e.to_string())
     });
-    
+
     (rx, handle)
 }
 
-/// Synchronous version for use with spawn_blocking (without progress)
+/// Synchronous version for use with spawn_blocking (without progress). See
+/// `separate_stems_with_progress` for why this does not retry internally.
 pub fn separate_stems_sync<P: AsRef<Path>>(
     audio_path: P,
     output_dir: P,
 ) -> Result<StemSeparationResult, String> {
-    let audio_str = audio_path.as_ref().to_str().ok_or("Invalid audio path")?;
-    let output_str = output_dir.as_ref().to_str().ok_or("Invalid output directory")?;
+    let audio_str = audio_path.as_ref().to_string_lossy().to_string();
+    let output_str = output_dir.as_ref().to_string_lossy().to_string();
 
-    // Spawn Python process with piped stderr for progress
-    let mut child = Command::new("../.venv/bin/python")
-        .arg("separate_stems.py")
+    run_separation(&audio_str, &output_str, None).map_err(|e| e.to_string())
+}
+
+/// Run a single Demucs invocation to completion: spawn the process, stream
+/// stderr (forwarding `PROGRESS:` lines over `tx` if given), watch for a
+/// stalled stage, and parse the resulting JSON from stdout.
+fn run_separation(
+    audio_str: &str,
+    output_str: &str,
+    tx: Option<&mpsc::Sender<StemProgress>>,
+) -> Result<StemSeparationResult, SeparationError> {
+    let python = python_env::resolve_python().map_err(SeparationError::ProcessFailed)?;
+    let script = python_env::resolve_script(&python, "separate_stems.py");
+    let mut child = Command::new(&python)
+        .arg(&script)
         .arg(audio_str)
         .arg(output_str)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|e| format!("Failed to execute python script: {}", e))?;
-
-    // Read stderr for progress updates
-    if let Some(stderr) = child.stderr.take() {
-        let reader = BufReader::new(stderr);
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                // Log all stderr for debugging
+        .map_err(|e| SeparationError::ProcessFailed(format!("Failed to execute python script: {}", e)))?;
+
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    let finished = Arc::new(AtomicBool::new(false));
+
+    // Watchdog: logs a warning (with elapsed time) whenever the process goes
+    // quiet for longer than the stall threshold, instead of hanging silently.
+    let watchdog = {
+        let last_activity = last_activity.clone();
+        let finished = finished.clone();
+        std::thread::spawn(move || {
+            let threshold = stall_threshold();
+            let mut warned_since_activity = false;
+            while !finished.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_secs(5));
+                let elapsed = last_activity.lock().unwrap().elapsed();
+                if elapsed >= threshold {
+                    if !warned_since_activity {
+                        eprintln!(
+                            "[Demucs] stage appears stalled: no progress for {:?} (threshold {:?})",
+                            elapsed, threshold
+                        );
+                        warned_since_activity = true;
+                    }
+                } else {
+                    warned_since_activity = false;
+                }
+            }
+        })
+    };
+
+    // Read stderr for progress updates in a separate thread
+    let stderr_handle = child.stderr.take().map(|stderr| {
+        let tx = tx.cloned();
+        let last_activity = last_activity.clone();
+        std::thread::spawn(move || {
+            let reader = BufReader::new(stderr);
+            for line in reader.lines().map_while(Result::ok) {
+                *last_activity.lock().unwrap() = Instant::now();
+
+                // Parse progress lines: "PROGRESS:50:Separating stems"
+                if line.starts_with("PROGRESS:") {
+                    let parts: Vec<&str> = line.splitn(3, ':').collect();
+                    if parts.len() == 3 {
+                        if let Ok(progress) = parts[1].parse::<u8>() {
+                            let stage = parts[2].to_string();
+                            if let Some(tx) = &tx {
+                                let _ = tx.send(StemProgress { progress, stage });
+                            }
+                        }
+                    }
+                }
+                // Also log other stderr lines for debugging
                 eprintln!("[Demucs] {}", line);
             }
-        }
-    }
+        })
+    });
 
     // Wait for process to finish and get stdout
     let output = child
         .wait_with_output()
-        .map_err(|e| format!("Failed to wait for python script: {}", e))?;
+        .map_err(|e| SeparationError::ProcessFailed(format!("Failed to wait for python script: {}", e)))?;
+
+    finished.store(true, Ordering::Relaxed);
+    let _ = watchdog.join();
+    if let Some(h) = stderr_handle {
+        let _ = h.join();
+    }
 
     if !output.status.success() {
-        return Err(format!(
+        return Err(SeparationError::ProcessFailed(format!(
             "stem separation failed with exit code: {:?}",
             output.status.code()
-        ));
+        )));
     }
 
     // Parse JSON output from stdout
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let result: StemSeparationResult = serde_json::from_str(&stdout)
-        .map_err(|e| format!("Failed to parse JSON output: {} (Output: {})", e, stdout))?;
+    let result: StemSeparationResult = serde_json::from_str(&stdout).map_err(|e| {
+        SeparationError::InvalidOutput(format!("Failed to parse JSON output: {} (Output: {})", e, stdout))
+    })?;
 
     if !result.success {
-        return Err(result.error.unwrap_or_else(|| "Unknown error".to_string()));
+        return Err(SeparationError::ProcessFailed(
+            result.error.unwrap_or_else(|| "Unknown error".to_string()),
+        ));
     }
 
     Ok(result)
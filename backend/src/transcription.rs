@@ -0,0 +1,155 @@
+//! Pluggable speech-to-text backend for vocal-stem lyric transcription.
+//! Mirrors `llm`'s pluggable-provider shape: a `TranscriptionProvider` trait
+//! implemented by either a local Whisper binding (run the same way the
+//! project's other Python steps are — through the venv) or a remote
+//! OpenAI-compatible `/v1/audio/transcriptions` endpoint, selected via
+//! `provider_from_env`.
+
+use crate::python_env;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::sync::Arc;
+
+/// A single timestamped lyric segment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    pub start_sec: f32,
+    pub end_sec: f32,
+    pub text: String,
+}
+
+#[async_trait]
+pub trait TranscriptionProvider: Send + Sync {
+    async fn transcribe(&self, audio_path: &str) -> Result<Vec<TranscriptSegment>, String>;
+}
+
+#[derive(Deserialize)]
+struct ScriptOutput {
+    segments: Vec<TranscriptSegment>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Runs `transcribe_vocals.py` with the interpreter `python_env` resolves —
+/// same subprocess convention as `analyzer_server` and `stem_separation`.
+pub struct LocalWhisperProvider;
+
+#[async_trait]
+impl TranscriptionProvider for LocalWhisperProvider {
+    async fn transcribe(&self, audio_path: &str) -> Result<Vec<TranscriptSegment>, String> {
+        let audio_path = audio_path.to_string();
+        tokio::task::spawn_blocking(move || run_local_whisper(&audio_path))
+            .await
+            .map_err(|e| format!("Task panic: {}", e))?
+    }
+}
+
+fn run_local_whisper(audio_path: &str) -> Result<Vec<TranscriptSegment>, String> {
+    let python = python_env::resolve_python()?;
+    let script = python_env::resolve_script(&python, "transcribe_vocals.py");
+    let output = Command::new(&python)
+        .arg(&script)
+        .arg(audio_path)
+        .output()
+        .map_err(|e| format!("Failed to execute python script: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Python script failed: {}", stderr));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result: ScriptOutput = serde_json::from_str(&stdout)
+        .map_err(|e| format!("Failed to parse JSON output: {} (Output: {})", e, stdout))?;
+
+    if let Some(err) = result.error {
+        return Err(format!("Transcription error: {}", err));
+    }
+
+    Ok(result.segments)
+}
+
+#[derive(Deserialize)]
+struct VerboseJsonSegment {
+    start: f32,
+    end: f32,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct VerboseJsonResponse {
+    #[serde(default)]
+    segments: Vec<VerboseJsonSegment>,
+}
+
+/// Talks to any server implementing the OpenAI `/v1/audio/transcriptions`
+/// shape, requesting `verbose_json` to get back per-segment timestamps.
+pub struct OpenAiCompatibleTranscriptionProvider {
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiCompatibleTranscriptionProvider {
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>, api_key: Option<String>) -> Self {
+        Self { base_url: base_url.into(), model: model.into(), api_key }
+    }
+}
+
+#[async_trait]
+impl TranscriptionProvider for OpenAiCompatibleTranscriptionProvider {
+    async fn transcribe(&self, audio_path: &str) -> Result<Vec<TranscriptSegment>, String> {
+        let bytes = tokio::fs::read(audio_path).await.map_err(|e| format!("Failed to read {}: {}", audio_path, e))?;
+        let file_name = std::path::Path::new(audio_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "audio.wav".to_string());
+
+        let form = reqwest::multipart::Form::new()
+            .text("model", self.model.clone())
+            .text("response_format", "verbose_json")
+            .part("file", reqwest::multipart::Part::bytes(bytes).file_name(file_name));
+
+        let client = reqwest::Client::new();
+        let mut req = client.post(&self.base_url).multipart(form);
+        if let Some(api_key) = &self.api_key {
+            req = req.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let res = req.send().await.map_err(|e| format!("Request failed: {}", e))?;
+
+        if !res.status().is_success() {
+            let error_text = res.text().await.unwrap_or_default();
+            return Err(format!("Transcription backend error: {}", error_text));
+        }
+
+        let body: VerboseJsonResponse = res.json().await.map_err(|e| format!("Parse error: {}", e))?;
+
+        Ok(body
+            .segments
+            .into_iter()
+            .map(|s| TranscriptSegment { start_sec: s.start, end_sec: s.end, text: s.text })
+            .collect())
+    }
+}
+
+/// Build the configured provider from environment variables:
+/// - `MIXANALYZER_TRANSCRIPTION_BASE_URL` switches to a remote
+///   OpenAI-compatible `/v1/audio/transcriptions` endpoint (the full URL,
+///   same convention as `MIXANALYZER_LLM_BASE_URL`); unset uses the local
+///   Whisper binding, so transcription works out of the box with the
+///   project's existing Python venv.
+/// - `MIXANALYZER_TRANSCRIPTION_MODEL` overrides the model id sent to a
+///   remote endpoint (defaults to `whisper-1`); unused for the local binding.
+/// - `OPENAI_API_KEY` is sent as a bearer token to a remote endpoint when
+///   present.
+pub fn provider_from_env() -> Arc<dyn TranscriptionProvider> {
+    if let Ok(base_url) = std::env::var("MIXANALYZER_TRANSCRIPTION_BASE_URL") {
+        let model = std::env::var("MIXANALYZER_TRANSCRIPTION_MODEL").unwrap_or_else(|_| "whisper-1".to_string());
+        let api_key = std::env::var("OPENAI_API_KEY").ok();
+        return Arc::new(OpenAiCompatibleTranscriptionProvider::new(base_url, model, api_key));
+    }
+
+    Arc::new(LocalWhisperProvider)
+}